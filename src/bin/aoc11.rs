@@ -1,6 +1,6 @@
 use std::fs;
 use std::env;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -15,6 +15,19 @@ fn main() {
 
     println!("Result: {}", value);
     println!("Result2: {}", value2);
+
+    let distribution = distribution_after_progression(&stones, 75);
+    let settling = find_settling(&stones);
+    println!("Distinct values after 75 blinks: {}", distribution.len());
+    println!("Distinct values reachable: {}", reachable_values(&stones).len());
+    println!("Settles after {} blinks into {} values (period {})", settling.start, settling.values.len(), settling.period);
+
+    // optional: count for a very large number of blinks, modulo a given value
+    if let Some(steps) = args.get(2) {
+        let steps: u64 = steps.parse().unwrap();
+        let modulus: u64 = args.get(3).map(|m| m.parse().unwrap()).unwrap_or(1_000_000_007);
+        println!("Count after {} blinks (mod {}): {}", steps, modulus, count_after_progression_mod(&stones, steps, modulus));
+    }
 }
 
 fn split_equal(n: usize) -> Option<(usize,usize)> {
@@ -27,27 +40,146 @@ fn split_equal(n: usize) -> Option<(usize,usize)> {
     }
 }
 
-fn count_instances(stone: usize, steps: usize, cache: &mut HashMap<(usize,usize),usize>) -> usize {
-    if let Some(&value) = cache.get(&(stone,steps)) {
-        return value;
-    }
-    let value;
-    if steps == 0 {
-        value = 1
-    } else if stone == 0 {
-        value = count_instances(1, steps-1, cache)
+fn blink(stone: usize) -> Vec<usize> {
+    if stone == 0 {
+        vec![1]
     } else if let Some((left,right)) = split_equal(stone) {
-        value = count_instances(left, steps-1, cache) + count_instances(right, steps-1, cache)
+        vec![left, right]
     } else {
-        value = count_instances(stone*2024, steps-1, cache)
+        vec![stone*2024]
+    }
+}
+
+fn initial_distribution(stones: &[usize]) -> HashMap<usize,usize> {
+    let mut distribution = HashMap::new();
+    for &stone in stones {
+        *distribution.entry(stone).or_default() += 1;
+    }
+    distribution
+}
+
+fn blink_distribution(distribution: &HashMap<usize,usize>) -> HashMap<usize,usize> {
+    let mut res = HashMap::new();
+    for (&stone, &count) in distribution {
+        for next in blink(stone) {
+            *res.entry(next).or_default() += count;
+        }
+    }
+    res
+}
+
+// histogram of stone values after the given number of blinks
+fn distribution_after_progression(stones: &[usize], steps: usize) -> HashMap<usize,usize> {
+    let mut distribution = initial_distribution(stones);
+    for _ in 0..steps {
+        distribution = blink_distribution(&distribution);
     }
-    cache.insert((stone,steps), value);
-    value
+    distribution
 }
 
 fn count_after_progression(stones: &[usize], steps: usize) -> usize {
-    let mut cache = HashMap::new();
-    stones.iter().map(|s| count_instances(*s, steps, &mut cache)).sum()
+    distribution_after_progression(stones, steps).values().sum()
+}
+
+// every value that can appear at some point, sorted
+fn reachable_values(stones: &[usize]) -> Vec<usize> {
+    let mut seen: HashSet<usize> = stones.iter().copied().collect();
+    let mut to_visit: Vec<usize> = seen.iter().copied().collect();
+    while let Some(stone) = to_visit.pop() {
+        for next in blink(stone) {
+            if seen.insert(next) {
+                to_visit.push(next);
+            }
+        }
+    }
+    let mut res: Vec<usize> = seen.into_iter().collect();
+    res.sort();
+    res
+}
+
+#[derive(Debug, PartialEq)]
+struct Settling {
+    start: usize,
+    period: usize,
+    values: Vec<usize>,
+}
+
+// the sets of values present after each blink end up cycling (in practice with period 1)
+fn find_settling(stones: &[usize]) -> Settling {
+    let mut history: Vec<Vec<usize>> = vec![];
+    let mut seen_at: HashMap<Vec<usize>,usize> = HashMap::new();
+    let mut distribution = initial_distribution(stones);
+    loop {
+        let mut present: Vec<usize> = distribution.keys().copied().collect();
+        present.sort();
+        if let Some(&start) = seen_at.get(&present) {
+            let mut values: Vec<usize> = history[start..].concat();
+            values.sort();
+            values.dedup();
+            return Settling{start, period: history.len()-start, values};
+        }
+        seen_at.insert(present.clone(), history.len());
+        history.push(present);
+        distribution = blink_distribution(&distribution);
+    }
+}
+
+fn mat_mul(a: &[Vec<u64>], b: &[Vec<u64>], modulus: u64) -> Vec<Vec<u64>> {
+    let n = a.len();
+    let mut res = vec![vec![0; n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            if a[i][k] == 0 {
+                continue;
+            }
+            for j in 0..n {
+                if b[k][j] != 0 {
+                    res[i][j] = ((res[i][j] as u128 + a[i][k] as u128 * b[k][j] as u128) % modulus as u128) as u64;
+                }
+            }
+        }
+    }
+    res
+}
+
+fn mat_vec_mul(a: &[Vec<u64>], v: &[u64], modulus: u64) -> Vec<u64> {
+    a.iter()
+        .map(|row| row.iter()
+            .zip(v)
+            .fold(0u128, |acc, (&x,&y)| (acc + x as u128 * y as u128) % modulus as u128) as u64)
+        .collect()
+}
+
+// number of stones after the given number of blinks, modulo `modulus`,
+// using exponentiation of the transition matrix over the reachable values
+fn count_after_progression_mod(stones: &[usize], steps: u64, modulus: u64) -> u64 {
+    let values = reachable_values(stones);
+    let index: HashMap<usize,usize> = values.iter().enumerate().map(|(i,&v)| (v,i)).collect();
+    let n = values.len();
+
+    let mut transition = vec![vec![0; n]; n];
+    for (i, &stone) in values.iter().enumerate() {
+        for next in blink(stone) {
+            transition[index[&next]][i] += 1;
+        }
+    }
+
+    let mut counts = vec![0; n];
+    for &stone in stones {
+        counts[index[&stone]] += 1;
+    }
+
+    let mut remaining = steps;
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            counts = mat_vec_mul(&transition, &counts, modulus);
+        }
+        remaining >>= 1;
+        if remaining > 0 {
+            transition = mat_mul(&transition, &transition, modulus);
+        }
+    }
+    (counts.iter().map(|&c| c as u128).sum::<u128>() % modulus as u128) as u64
 }
 
 #[cfg(test)]
@@ -59,4 +191,60 @@ mod tests {
         assert_eq!(count_after_progression(&[125,17], 6), 22);
         assert_eq!(count_after_progression(&[125,17], 25), 55312);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn example_distribution() {
+        let distribution = distribution_after_progression(&[125,17], 6);
+        // 2097446912 14168 4048 2 0 2 4 40 48 2024 40 48 80 96 2 8 6 7 6 0 3 2
+        assert_eq!(distribution[&2], 4);
+        assert_eq!(distribution[&0], 2);
+        assert_eq!(distribution[&6], 2);
+        assert_eq!(distribution[&40], 2);
+        assert_eq!(distribution[&48], 2);
+        assert_eq!(distribution.len(), 15);
+    }
+
+    #[test]
+    fn example_settling() {
+        assert_eq!(reachable_values(&[0]).len(), 54);
+        assert_eq!(reachable_values(&[125,17]).len(), 76);
+
+        let settling = find_settling(&[125,17]);
+        assert_eq!(settling.period, 1);
+        assert_eq!(settling.values, reachable_values(&[0]));
+        let distribution = distribution_after_progression(&[125,17], settling.start+10);
+        let mut present: Vec<usize> = distribution.keys().copied().collect();
+        present.sort();
+        assert_eq!(present, settling.values);
+    }
+
+    #[test]
+    fn example_matrix() {
+        for steps in [0, 1, 6, 25, 75] {
+            let expected = (count_after_progression(&[125,17], steps as usize) % 1_000_000_007) as u64;
+            assert_eq!(count_after_progression_mod(&[125,17], steps, 1_000_000_007), expected);
+        }
+        assert_eq!(count_after_progression_mod(&[125,17], 25, u64::MAX), 55312);
+
+        // past what fits in a usize, against blinking a histogram kept modulo the same value
+        let modulus = 1_000_000_007;
+        let mut distribution: HashMap<usize,u64> = HashMap::from([(125, 1), (17, 1)]);
+        for _ in 0..500 {
+            let mut next: HashMap<usize,u64> = HashMap::new();
+            for (&stone, &count) in &distribution {
+                for stone in blink(stone) {
+                    let total = next.entry(stone).or_default();
+                    *total = (*total + count) % modulus;
+                }
+            }
+            distribution = next;
+        }
+        let expected = distribution.values().fold(0, |acc, &c| (acc + c) % modulus);
+        assert_eq!(count_after_progression_mod(&[125,17], 500, modulus), expected);
+
+        // sums of residues close to 2^64 must not overflow; the exact count after
+        // 300 blinks and the one after 10^12 blinks were computed separately
+        assert_eq!(count_after_progression_mod(&[125,17], 300, u64::MAX), 8393375904587586403);
+        assert_eq!(count_after_progression_mod(&[125,17], 1_000_000_000_000, modulus), 221045131);
+    }
+}
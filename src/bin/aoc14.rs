@@ -1,9 +1,14 @@
+#[cfg(test)]
+#[path = "common/lcg.rs"]
+mod lcg;
+
 use std::fs;
 use std::env;
 use regex::Regex;
 use itertools::Itertools;
 use lazy_static::lazy_static;
-//use std::{thread, time};
+use std::path::Path;
+use std::ops::Range;
 
 lazy_static! {
     static ref RE_BOT: Regex = Regex::new(r"p=(-?\d+),(-?\d+) v=(-?\d+),(-?\d+)").unwrap();
@@ -23,19 +28,69 @@ fn main() {
 
     println!("Result {}", result);
 
-    for i in 1..1000000 {
-        let moved = robots.iter().map(|r| simulate_moves(r, i, 101, 103)).collect::<Vec<(i32,i32)>>();
-        let variance = variances(&moved);
-        if variance < 900.0f32 {
-            /*
-            let pic = _make_picture(&moved);
-            println!("{}\nAfter {} moves, variance: {}", pic, i, variance);
-            std::thread::sleep(time::Duration::from_millis(500));
-            */
-            println!("Result2: {}", i);
-            break;
+    if let Some(i) = args.iter().position(|a| a == "--sectors") {
+        // safety factor over a columns x rows grid instead of the quadrants
+        let regions = sectors(101, 103, args[i+1].parse().unwrap(), args[i+2].parse().unwrap());
+        println!("Sector counts: {:?}", region_counts(&after_move, &regions));
+        println!("Sector safety factor: {}", partition_safety_factor(&after_move, &regions));
+    }
+    if let Some(i) = args.iter().position(|a| a == "--heatmap") {
+        for row in density_heatmap(&after_move, 101, 103, args[i+1].parse().unwrap()) {
+            println!("{}", row.iter().map(|c| format!("{:3}", c)).join(""));
+        }
+    }
+
+    println!("System period: {}", system_period(&robots, 101, 103));
+    let collisions = pair_collisions(&robots, 101, 103);
+    println!("Colliding pairs: {}", collisions.len());
+    if args.iter().any(|a| a == "--collisions") {
+        for (i, j, first, period) in &collisions {
+            println!("Robots {} and {} meet at steps {} + k * {}", i, j, first, period);
         }
     }
+    match first_distinct_step(&robots, 101, 103, SIEVE_WINDOW) {
+        Some(step) => println!("First step with all robots apart: {}", step),
+        None => println!("First step with all robots apart: never"),
+    }
+
+    // x positions repeat every width steps and y positions every height steps,
+    // so the picture is where both axes are the most packed
+    match picture_step_crt(&robots, 101, 103) {
+        Some(step) => println!("Result2: {}", step),
+        None => println!("Result2: no step fits both axes"),
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--detector") {
+        // scan a whole period with another heuristic instead
+        let detector: Box<dyn Detector> = match args[i+1].as_str() {
+            "variance" => Box::new(AxisVariance),
+            "entropy" => Box::new(Entropy{block: 5}),
+            "component" => Box::new(LargestComponent),
+            "run" => Box::new(LongestRun),
+            other => panic!("Unknown detector {}", other),
+        };
+        println!("Picture with {}: {}", args[i+1], find_picture(&robots, 101, 103, detector.as_ref()));
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--frames") {
+        // one image per step: --frames first last pbm|pgm|txt directory
+        let (first, last): (i32, i32) = (args[i+1].parse().unwrap(), args[i+2].parse().unwrap());
+        let dir = Path::new(&args[i+4]);
+        fs::create_dir_all(dir).unwrap();
+        for step in first..=last {
+            let frame = make_frame(&robots, step, 101, 103);
+            fs::write(dir.join(format!("step_{:06}.{}", step, args[i+3])), frame.render(&args[i+3])).unwrap();
+        }
+        println!("Wrote {} frames", last - first + 1);
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--sheet") {
+        // all steps on one image: --sheet first last columns pbm|pgm|txt file
+        let (first, last): (i32, i32) = (args[i+1].parse().unwrap(), args[i+2].parse().unwrap());
+        let frames: Vec<Frame> = (first..=last).map(|step| make_frame(&robots, step, 101, 103)).collect();
+        let sheet = contact_sheet(&frames, args[i+3].parse().unwrap(), 2);
+        fs::write(&args[i+5], sheet.render(&args[i+4])).unwrap();
+    }
 }
 
 struct RobotDescription {
@@ -63,53 +118,370 @@ fn simulate_moves(r: &RobotDescription, steps: i32, width: i32, height: i32) ->
     (end_x, end_y)
 }
 
-fn safety_factor(bots: &[(i32,i32)], width: i32, height: i32) -> i32 {
+// cells with x and y in the ranges
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Region {
+    x: Range<i32>,
+    y: Range<i32>
+}
+
+impl Region {
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        self.x.contains(&x) && self.y.contains(&y)
+    }
+}
+
+// the four quadrants around the middle row and column, which belong to none of them
+fn quadrants(width: i32, height: i32) -> Vec<Region> {
     let midx = (width-1)/2;
     let midy = (height-1)/2;
-    let quadrants = bots
-        .iter()
-        .sorted_by_key(|(x,y)| (x.cmp(&midx), y.cmp(&midy)))
-        .chunk_by(|(x,y)| (x.cmp(&midx), y.cmp(&midy)));
+    [0..midy, midy+1..height].into_iter()
+        .cartesian_product([0..midx, midx+1..width])
+        .map(|(y, x)| Region{x, y})
+        .collect()
+}
+
+// columns x rows sectors covering the whole grid, row by row, as even as possible
+fn sectors(width: i32, height: i32, columns: i32, rows: i32) -> Vec<Region> {
+    let split = |size: i32, parts: i32| (0..parts).map(move |k| k*size/parts..(k+1)*size/parts);
+    split(height, rows)
+        .cartesian_product(split(width, columns).collect::<Vec<_>>())
+        .map(|(y, x)| Region{x, y})
+        .collect()
+}
+
+// robots in each region, a robot in overlapping regions counts for all of them
+fn region_counts(bots: &[(i32,i32)], regions: &[Region]) -> Vec<usize> {
+    regions.iter()
+        .map(|region| bots.iter().filter(|bot| region.contains(**bot)).count())
+        .collect()
+}
+
+fn partition_safety_factor(bots: &[(i32,i32)], regions: &[Region]) -> u64 {
+    region_counts(bots, regions).iter().map(|c| *c as u64).product()
+}
 
-    let mut result = 1;
-    for (key, it) in quadrants.into_iter() {
-        if key.0 != std::cmp::Ordering::Equal && key.1 != std::cmp::Ordering::Equal {
-            /*
-            let in_group: Vec<(i32,i32)> = it.cloned().collect();
-            println!("In quadarant {:?} at {:?}", key, in_group);
-            result *= in_group.len() as i32;
-            */
-            result *= it.count() as i32;
+fn safety_factor(bots: &[(i32,i32)], width: i32, height: i32) -> i32 {
+    partition_safety_factor(bots, &quadrants(width, height)) as i32
+}
+
+// robots per block x block square, the last row and column may be cut short
+fn density_heatmap(bots: &[(i32,i32)], width: i32, height: i32, block: i32) -> Vec<Vec<usize>> {
+    let columns = (width + block - 1) / block;
+    (0..height).step_by(block as usize)
+        .map(|top| {
+            let regions: Vec<Region> = (0..columns)
+                .map(|k| Region{x: k*block..((k+1)*block).min(width), y: top..(top+block).min(height)})
+                .collect();
+            region_counts(bots, &regions)
+        })
+        .collect()
+}
+
+// robot count per cell, row by row
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Frame {
+    width: usize,
+    height: usize,
+    counts: Vec<u32>
+}
+
+fn make_frame(robots: &[RobotDescription], steps: i32, width: i32, height: i32) -> Frame {
+    let mut counts = vec![0; (width * height) as usize];
+    for r in robots {
+        let (x, y) = simulate_moves(r, steps, width, height);
+        counts[(y * width + x) as usize] += 1;
+    }
+    Frame{ width: width as usize, height: height as usize, counts }
+}
+
+impl Frame {
+    fn rows(&self) -> std::slice::Chunks<'_, u32> {
+        self.counts.chunks(self.width)
+    }
+
+    // black pixel wherever there is a robot
+    fn to_pbm(&self) -> String {
+        let mut res = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.rows() {
+            res += &row.iter().map(|c| if *c > 0 {"1"} else {"0"}).join(" ");
+            res += "\n";
+        }
+        res
+    }
+
+    // grey level is the robot count, brightest for the most crowded cell
+    fn to_pgm(&self) -> String {
+        let max = self.counts.iter().max().copied().unwrap_or(0).max(1);
+        let mut res = format!("P2\n{} {}\n{}\n", self.width, self.height, max);
+        for row in self.rows() {
+            res += &row.iter().join(" ");
+            res += "\n";
+        }
+        res
+    }
+
+    // same layout as the puzzle description, counts above 9 show as +
+    fn to_text(&self) -> String {
+        let mut res = String::new();
+        for row in self.rows() {
+            res.extend(row.iter().map(|c| match c {
+                0 => '.',
+                1..=9 => char::from_digit(*c, 10).unwrap(),
+                _ => '+',
+            }));
+            res += "\n";
         }
+        res
     }
 
-    result
+    fn render(&self, format: &str) -> String {
+        match format {
+            "pbm" => self.to_pbm(),
+            "pgm" => self.to_pgm(),
+            "txt" => self.to_text(),
+            other => panic!("Unknown frame format {}", other),
+        }
+    }
 }
 
-fn _make_picture(bots: &[(i32,i32)]) -> String {
-    let mut res: String = String::new();
-    for i in 0..103 {
-        let s: String = (0..101)
-            .map(|j| bots.contains(&(i,j)))
-            .map(|v| if v {'#'} else {'.'})
-            .collect();
-        res += "\n";
-        res += s.as_str();
+// tiles frames of the same size left to right then top to bottom, with empty
+// cells between them; a strip is a sheet with as many columns as frames
+fn contact_sheet(frames: &[Frame], columns: usize, gap: usize) -> Frame {
+    let (width, height) = frames.first().map_or((0, 0), |f| (f.width, f.height));
+    let rows = frames.len().div_ceil(columns);
+    let sheet_width = (columns * (width + gap)).saturating_sub(gap);
+    let sheet_height = (rows * (height + gap)).saturating_sub(gap);
+    let mut counts = vec![0; sheet_width * sheet_height];
+    for (k, frame) in frames.iter().enumerate() {
+        let (left, top) = ((k % columns) * (width + gap), (k / columns) * (height + gap));
+        for (y, row) in frame.rows().enumerate() {
+            let start = (top + y) * sheet_width + left;
+            counts[start..start + width].copy_from_slice(row);
+        }
     }
-    res
+    Frame{ width: sheet_width, height: sheet_height, counts }
 }
 
 fn variances(bots: &[(i32,i32)]) -> f32 {
-    let meanx: f32 = bots.iter().map(|(x,_)| *x as f32).sum::<f32>() / (bots.len() as f32);
-    let meany = bots.iter().map(|(_,y)| *y as f32).sum::<f32>() / (bots.len() as f32);
-    let varx = bots.iter().map(|(x,_)| (*x as f32-meanx)*(*x as f32-meanx)).sum::<f32>() / (bots.len() as f32);
-    let vary = bots.iter().map(|(_,y)| (*y as f32-meany)*(*y as f32-meany)).sum::<f32>() / (bots.len() as f32);
-    varx + vary
+    axis_variance(bots.iter().map(|(x,_)| *x)) + axis_variance(bots.iter().map(|(_,y)| *y))
+}
+
+fn axis_variance(values: impl Iterator<Item = i32> + Clone) -> f32 {
+    let count = values.clone().count() as f32;
+    let mean = values.clone().map(|v| v as f32).sum::<f32>() / count;
+    values.map(|v| (v as f32-mean)*(v as f32-mean)).sum::<f32>() / count
+}
+
+// scores how much a frame looks like a picture, higher is better
+trait Detector {
+    fn score(&self, bots: &[(i32,i32)], width: i32, height: i32) -> f64;
+}
+
+// robots gathered in one place
+struct AxisVariance;
+
+impl Detector for AxisVariance {
+    fn score(&self, bots: &[(i32,i32)], _width: i32, _height: i32) -> f64 {
+        -variances(bots) as f64
+    }
+}
+
+// robots spread over few blocks of block x block cells
+struct Entropy {
+    block: i32
+}
+
+impl Detector for Entropy {
+    fn score(&self, bots: &[(i32,i32)], width: i32, _height: i32) -> f64 {
+        let columns = (width + self.block - 1) / self.block;
+        let counts = bots.iter().counts_by(|(x,y)| (y / self.block) * columns + x / self.block);
+        let total = bots.len() as f64;
+        counts.values().map(|&c| c as f64 / total).map(|p| p * p.ln()).sum()
+    }
+}
+
+// robots touching each other, as the size of the largest 4-connected group
+struct LargestComponent;
+
+impl Detector for LargestComponent {
+    fn score(&self, bots: &[(i32,i32)], width: i32, height: i32) -> f64 {
+        let index = |(x,y): (i32,i32)| (y * width + x) as usize;
+        let mut occupied = vec![false; (width * height) as usize];
+        for bot in bots {
+            occupied[index(*bot)] = true;
+        }
+        let mut largest = 0;
+        for bot in bots {
+            if !occupied[index(*bot)] {
+                continue;
+            }
+            // clear cells as they are counted so every group is walked once
+            occupied[index(*bot)] = false;
+            let mut to_explore = vec![*bot];
+            let mut size = 0;
+            while let Some((x,y)) = to_explore.pop() {
+                size += 1;
+                for next in [(x-1,y), (x+1,y), (x,y-1), (x,y+1)] {
+                    if next.0 >= 0 && next.0 < width && next.1 >= 0 && next.1 < height && occupied[index(next)] {
+                        occupied[index(next)] = false;
+                        to_explore.push(next);
+                    }
+                }
+            }
+            largest = largest.max(size);
+        }
+        largest as f64
+    }
+}
+
+// robots lined up, as the longest run of occupied cells in a row
+struct LongestRun;
+
+impl Detector for LongestRun {
+    fn score(&self, bots: &[(i32,i32)], _width: i32, _height: i32) -> f64 {
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous = None;
+        for (x,y) in bots.iter().map(|(x,y)| (*x,*y)).sorted_by_key(|(x,y)| (*y,*x)).dedup() {
+            run = if previous == Some((x-1,y)) { run + 1 } else { 1 };
+            longest = longest.max(run);
+            previous = Some((x,y));
+        }
+        longest as f64
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+// returns (g, u, v) with a*u + b*v = g = gcd(a, b)
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, u, v) = extended_gcd(b, a % b);
+        (g, v, u - (a / b) * v)
+    }
+}
+
+// smallest t >= 0 with t = a1 mod m1 and t = a2 mod m2, with the combined modulus
+fn crt(a1: i64, m1: i64, a2: i64, m2: i64) -> Option<(i64, i64)> {
+    let (g, u, _) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let lcm = m1 / g * m2;
+    let k = ((a2 - a1) / g % (m2 / g) * u % (m2 / g)) as i128;
+    let t = (a1 as i128 + m1 as i128 * k).rem_euclid(lcm as i128) as i64;
+    Some((t, lcm))
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
+
+// solutions of a*t = b mod m, as t = first mod period
+fn solve_linear(a: i64, b: i64, m: i64) -> Option<(i64, i64)> {
+    let (a, b) = (a.rem_euclid(m), b.rem_euclid(m));
+    let (g, u, _) = extended_gcd(a, m);
+    let g = g.abs();
+    if b % g != 0 {
+        return None;
+    }
+    let period = m / g;
+    Some(((b / g) as i128 * u as i128).rem_euclid(period as i128) as i64).map(|first| (first, period))
+}
+
+// steps after which a robot is back where it started
+fn robot_period(r: &RobotDescription, width: i32, height: i32) -> i64 {
+    let axis = |speed: i32, size: i32| size as i64 / gcd(speed.rem_euclid(size) as i64, size as i64);
+    lcm(axis(r.speed.0, width), axis(r.speed.1, height))
+}
+
+// steps after which every robot is back where it started
+fn system_period(robots: &[RobotDescription], width: i32, height: i32) -> i64 {
+    robots.iter().map(|r| robot_period(r, width, height)).fold(1, lcm)
+}
+
+// two robots share a cell exactly at the steps first + k * period, if ever
+fn collision_steps(a: &RobotDescription, b: &RobotDescription, width: i32, height: i32) -> Option<(i64, i64)> {
+    let (x, period_x) = solve_linear((a.speed.0 - b.speed.0) as i64, (b.origin.0 - a.origin.0) as i64, width as i64)?;
+    let (y, period_y) = solve_linear((a.speed.1 - b.speed.1) as i64, (b.origin.1 - a.origin.1) as i64, height as i64)?;
+    crt(x, period_x, y, period_y)
+}
+
+// every pair of robots that ever share a cell, with the steps they do as in collision_steps
+fn pair_collisions(robots: &[RobotDescription], width: i32, height: i32) -> Vec<(usize, usize, i64, i64)> {
+    (0..robots.len())
+        .tuple_combinations()
+        .filter_map(|(i, j)| collision_steps(&robots[i], &robots[j], width, height).map(|(first, period)| (i, j, first, period)))
+        .collect()
+}
+
+// steps sieved at once by first_distinct_step, bounds its memory whatever the period
+const SIEVE_WINDOW: i64 = 1 << 16;
+
+// marks the colliding steps of every pair over one system period, the pattern repeats
+// after that; the period is sieved window steps at a time so the time is O(period) at
+// worst but the memory stays O(window), and the scan stops at the first free step
+fn first_distinct_step(robots: &[RobotDescription], width: i32, height: i32, window: i64) -> Option<i64> {
+    let period = system_period(robots, width, height);
+    let collisions = pair_collisions(robots, width, height);
+    let mut crowded = vec![false; window.min(period) as usize];
+    for start in (0..period).step_by(window as usize) {
+        let end = (start + window).min(period);
+        crowded.iter_mut().for_each(|c| *c = false);
+        for &(_, _, first, pair_period) in &collisions {
+            // first colliding step of the pair inside the window
+            let from = if first >= start { first } else { first + (start - first + pair_period - 1) / pair_period * pair_period };
+            for step in (from..end).step_by(pair_period as usize) {
+                crowded[(step - start) as usize] = true;
+            }
+        }
+        if let Some(step) = crowded[..(end - start) as usize].iter().position(|c| !c) {
+            return Some(start + step as i64);
+        }
+    }
+    None
+}
+
+// step in a whole period of the swarm with the best detector score, the earliest on ties
+fn find_picture(robots: &[RobotDescription], width: i32, height: i32, detector: &dyn Detector) -> i32 {
+    let period = (width as i64 * height as i64 / gcd(width as i64, height as i64)) as i32;
+    let mut best = (f64::NEG_INFINITY, 0);
+    for step in 0..period {
+        let moved: Vec<(i32,i32)> = robots.iter().map(|r| simulate_moves(r, step, width, height)).collect();
+        let score = detector.score(&moved, width, height);
+        if score > best.0 {
+            best = (score, step);
+        }
+    }
+    best.1
+}
+
+// step in 0..period where one coordinate of the robots has the least variance
+fn tightest_axis_step(robots: &[RobotDescription], period: i32, axis: impl Fn(&RobotDescription) -> (i32,i32)) -> i32 {
+    let spread = |step: i32| axis_variance(robots.iter().map(|r| {
+        let (origin, speed) = axis(r);
+        (origin + step * speed).rem_euclid(period)
+    }));
+    (0..period).min_by(|a, b| spread(*a).total_cmp(&spread(*b))).unwrap()
+}
+
+// the x offset of the picture only matters mod width and the y offset mod height,
+// so find both separately and combine them with the chinese remainder theorem
+fn picture_step_crt(robots: &[RobotDescription], width: i32, height: i32) -> Option<i64> {
+    let step_x = tightest_axis_step(robots, width, |r| (r.origin.0, r.speed.0));
+    let step_y = tightest_axis_step(robots, height, |r| (r.origin.1, r.speed.1));
+    crt(step_x as i64, width as i64, step_y as i64, height as i64).map(|(step, _)| step)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::lcg::Lcg;
 
     #[test]
     fn example_move() {
@@ -125,4 +497,136 @@ mod tests {
         let safety = safety_factor(&after_move, 11, 7);
         assert_eq!(safety, 12);
     }
-}
\ No newline at end of file
+
+    // a swarm forming a filled triangle with a frame at `step`, plus scattered robots
+    fn picture_swarm(width: i32, height: i32, step: i32) -> Vec<RobotDescription> {
+        let mut rng = Lcg(17);
+        let mut random = |n: i32| (rng.next() % n as u64) as i32;
+        let mut cells = vec![];
+        for y in 0..20 {
+            cells.push((5, 5 + y));
+            cells.push((35, 5 + y));
+            for x in 20 - y/2..=20 + y/2 {
+                cells.push((x, 5 + y));
+            }
+        }
+        cells.extend((5..=35).flat_map(|x| [(x, 4), (x, 25)]));
+        for _ in 0..60 {
+            cells.push((random(width), random(height)));
+        }
+        cells.into_iter().map(|(x,y)| {
+            let speed = (random(201) - 100, random(201) - 100);
+            RobotDescription{
+                origin: ((x - step * speed.0).rem_euclid(width), (y - step * speed.1).rem_euclid(height)),
+                speed,
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn chinese_remainder() {
+        assert_eq!(crt(2, 3, 3, 5), Some((8, 15)));
+        assert_eq!(crt(0, 101, 0, 103), Some((0, 10403)));
+        assert_eq!(crt(3, 4, 1, 6), Some((7, 12)));
+        assert_eq!(crt(1, 4, 2, 6), None);
+        for (x, y) in [(17, 5), (100, 102), (0, 57)] {
+            let (t, _) = crt(x, 101, y, 103).unwrap();
+            assert_eq!((t % 101, t % 103), (x, y));
+        }
+    }
+
+    #[test]
+    fn picture_detectors() {
+        assert_eq!(picture_step_crt(&picture_swarm(101, 103, 7753), 101, 103), Some(7753));
+
+        // full scans on a smaller grid to keep the test fast
+        let robots = picture_swarm(41, 37, 1234);
+        assert_eq!(picture_step_crt(&robots, 41, 37), Some(1234));
+        assert_eq!(find_picture(&robots, 41, 37, &AxisVariance), 1234);
+        assert_eq!(find_picture(&robots, 41, 37, &Entropy{block: 5}), 1234);
+        assert_eq!(find_picture(&robots, 41, 37, &LargestComponent), 1234);
+        assert_eq!(find_picture(&robots, 41, 37, &LongestRun), 1234);
+    }
+
+    #[test]
+    fn frame_export() {
+        let txt = "p=0,4 v=3,-3\np=6,3 v=-1,-3\np=10,3 v=-1,2\np=2,0 v=2,-1\np=0,0 v=1,3\np=3,0 v=-2,-2\np=7,6 v=-1,-3\np=3,0 v=-1,-2\np=9,3 v=2,3\np=7,3 v=-1,2\np=2,4 v=2,-3\np=9,5 v=-3,-3";
+        let robots: Vec<RobotDescription> = txt.split('\n').map(parse_robot).collect();
+        let frame = make_frame(&robots, 100, 11, 7);
+        assert_eq!(frame.to_text(), "......2..1.\n...........\n1..........\n.11........\n.....1.....\n...12......\n.1....1....\n");
+        assert!(frame.to_pbm().starts_with("P1\n11 7\n0 0 0 0 0 0 1 0 0 1 0\n"));
+        assert!(frame.to_pgm().starts_with("P2\n11 7\n2\n0 0 0 0 0 0 2 0 0 1 0\n"));
+
+        let frames: Vec<Frame> = (0..3).map(|step| make_frame(&robots, step, 11, 7)).collect();
+        let strip = contact_sheet(&frames, 3, 1);
+        assert_eq!((strip.width, strip.height), (35, 7));
+        let sheet = contact_sheet(&frames, 2, 1);
+        assert_eq!((sheet.width, sheet.height), (23, 15));
+        // third frame starts the second row of the sheet
+        let lines: Vec<String> = sheet.to_text().lines().map(String::from).collect();
+        let third: Vec<String> = frames[2].to_text().lines().map(String::from).collect();
+        assert_eq!(lines[8][..11], third[0]);
+        assert_eq!(lines[7], ".".repeat(23));
+        assert_eq!(lines[8][11..], ".".repeat(12));
+    }
+
+    #[test]
+    fn partitions() {
+        let txt = "p=0,4 v=3,-3\np=6,3 v=-1,-3\np=10,3 v=-1,2\np=2,0 v=2,-1\np=0,0 v=1,3\np=3,0 v=-2,-2\np=7,6 v=-1,-3\np=3,0 v=-1,-2\np=9,3 v=2,3\np=7,3 v=-1,2\np=2,4 v=2,-3\np=9,5 v=-3,-3";
+        let robots: Vec<RobotDescription> = txt.split('\n').map(parse_robot).collect();
+        let after_move: Vec<(i32,i32)> = robots.iter().map(|r| simulate_moves(r, 100, 11, 7)).collect();
+
+        assert_eq!(region_counts(&after_move, &quadrants(11, 7)), vec![1, 3, 4, 1]);
+        assert_eq!(partition_safety_factor(&after_move, &quadrants(11, 7)), 12);
+
+        // sectors leave no gap, so the middle robots count too
+        let regions = sectors(11, 7, 2, 2);
+        assert_eq!(regions[1], Region{x: 5..11, y: 0..3});
+        assert_eq!(region_counts(&after_move, &regions), vec![1, 3, 6, 2]);
+        assert_eq!(partition_safety_factor(&after_move, &regions), 36);
+        assert_eq!(region_counts(&after_move, &sectors(11, 7, 1, 1)), vec![12]);
+
+        let custom = [Region{x: 0..11, y: 0..1}, Region{x: 0..3, y: 0..7}];
+        assert_eq!(region_counts(&after_move, &custom), vec![3, 4]);
+
+        assert_eq!(density_heatmap(&after_move, 11, 7, 4), vec![vec![3, 2, 1], vec![2, 4, 0]]);
+    }
+
+    #[test]
+    fn periods_and_collisions() {
+        let mut rng = Lcg(5);
+        let mut random = |n: i32| (rng.next() % n as u64) as i32;
+        let txt = "p=0,4 v=3,-3\np=6,3 v=-1,-3\np=10,3 v=-1,2\np=2,0 v=2,-1\np=0,0 v=1,3\np=3,0 v=-2,-2\np=7,6 v=-1,-3\np=3,0 v=-1,-2\np=9,3 v=2,3\np=7,3 v=-1,2\np=2,4 v=2,-3\np=9,5 v=-3,-3";
+        let example: Vec<RobotDescription> = txt.split('\n').map(parse_robot).collect();
+        // sizes sharing factors make some robots cycle faster than width * height
+        let random_swarm: Vec<RobotDescription> = (0..10).map(|_| RobotDescription{
+            origin: (random(12), random(8)),
+            speed: (random(25) - 12, random(17) - 8),
+        }).collect();
+        assert_eq!(system_period(&example, 11, 7), 77);
+
+        for (robots, width, height) in [(&example[..], 11, 7), (&random_swarm[..], 12, 8), (&random_swarm[..4], 12, 8)] {
+            let positions = |step: i32| robots.iter().map(|r| simulate_moves(r, step, width, height)).collect::<Vec<_>>();
+            let period = system_period(robots, width, height);
+            assert_eq!(positions(period as i32), positions(0));
+            assert!((1..period).all(|step| positions(step as i32) != positions(0)));
+
+            for (i, j) in (0..robots.len()).tuple_combinations() {
+                let expected: Vec<i64> = (0..period)
+                    .filter(|step| simulate_moves(&robots[i], *step as i32, width, height) == simulate_moves(&robots[j], *step as i32, width, height))
+                    .collect();
+                let found: Vec<i64> = match collision_steps(&robots[i], &robots[j], width, height) {
+                    Some((first, pair_period)) => (first..period).step_by(pair_period as usize).collect(),
+                    None => vec![],
+                };
+                assert_eq!(found, expected);
+            }
+
+            let expected = (0..period).find(|step| positions(*step as i32).iter().all_unique());
+            // small windows cross window boundaries
+            for window in [1, 5, SIEVE_WINDOW] {
+                assert_eq!(first_distinct_step(robots, width, height, window), expected);
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::fs;
 use std::env;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
 
 
 fn main() {
@@ -10,11 +13,86 @@ fn main() {
     let contents = fs::read_to_string(filename)
         .expect("Should have been able to read the file");
 
-    let mut split_str = contents.split("\n\n");
-    let mut map = parse_map(split_str.next().unwrap());
+    let mut map = or_exit(check_input(&contents));
+    let mut split_str = contents.split("\n\n").skip(1);
     let mut double_map = double_map(&map);
+
+    if args.iter().any(|a| a == "--check") {
+        let stuck = stuck_crates(&map);
+        println!("Map is valid, {} boxes can never move", stuck.len());
+        for (i,j) in stuck {
+            println!("  box at row {}, column {}", i, j);
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--play") {
+        // drive the robot by hand, on the wide map with --double
+        let start = if args.iter().any(|a| a == "--double") { double_map } else { map };
+        let _raw = RawTerminal::enable().expect("Should have been able to set up the terminal");
+        let end = play(start, io::stdin().lock(), io::stdout().lock()).unwrap();
+        println!("Final checksum: {}", get_checksum(&end));
+        return;
+    }
+
     let moves = parse_directions(split_str.next().unwrap());
 
+    if map.bots.len() > 1 {
+        // one move block per robot
+        let mut scripts: Vec<Vec<Direction>> = vec![moves];
+        scripts.extend(split_str.map(parse_directions).filter(|m| !m.is_empty()));
+        let rounds = run_lockstep(&mut map, &scripts);
+        run_lockstep(&mut double_map, &scripts);
+        println!("Robots: {}, rounds: {}", map.bots.len(), rounds);
+        println!("Result: {}", get_checksum(&map));
+        println!("Result2: {}", get_checksum(&double_map));
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--replay") {
+        // replay the wide map with --double, then print the state after move N
+        // or the whole sequence, optionally writing PGM frames to a directory
+        let start = if args.iter().any(|a| a == "--double") { &double_map } else { &map };
+        let replay = Replay::record(start, &moves);
+        match args.get(i+1).and_then(|n| n.parse::<usize>().ok()) {
+            Some(n) => println!("{}", replay.state_at(n)._repr()),
+            None => print!("{}", replay.text_animation()),
+        }
+        if let Some(j) = args.iter().position(|a| a == "--frames") {
+            let count = replay.export_frames(Path::new(&args[j+1])).unwrap();
+            println!("Wrote {} frames", count);
+        }
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--compare") {
+        // compare against the run described by another input file
+        let other = fs::read_to_string(&args[i+1])
+            .expect("Should have been able to read the file");
+        let other_map = or_exit(check_input(&other));
+        let mut other_split = other.split("\n\n").skip(1);
+        let other_moves = parse_directions(other_split.next().unwrap());
+        let replay = Replay::record(&map, &moves);
+        let other_replay = Replay::record(&other_map, &other_moves);
+        match first_divergence(&replay, &other_replay) {
+            Some(n) => println!("Runs diverge after move {}", n),
+            None => println!("Runs agree over {} moves", replay.len().min(other_replay.len())),
+        }
+        return;
+    }
+
+    if let Some(i) = args.iter().position(|a| a == "--scale") {
+        // stretch the map by arbitrary factors before running the moves
+        let width = args[i+1].parse().unwrap();
+        let height = args.get(i+2).and_then(|h| h.parse().ok()).unwrap_or(1);
+        let mut scaled = scale_map(&map, width, height);
+        for m in moves {
+            try_move(&mut scaled, m);
+        }
+        println!("Scaled result: {}", get_checksum(&scaled));
+        return;
+    }
+
     for m in moves.clone() {
         try_move(&mut map, m);
     }
@@ -33,15 +111,43 @@ fn main() {
 enum Cell {
     Empty,
     Wall,
-    Box,
-    BoxLeft,
-    BoxRight
+    // every cell of a crate holds the same id, crates are connected shapes
+    Crate(usize)
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Eq, Clone, Debug)]
 struct Map {
     cells: Vec<Vec<Cell>>,
-    bot: (usize,usize)
+    // robots in reading order, the first one is driven by try_move
+    bots: Vec<(usize,usize)>
+}
+
+impl PartialEq for Map {
+    // crate ids depend on the parse order, so compare which cells belong together instead
+    fn eq(&self, other: &Map) -> bool {
+        if self.bots != other.bots || self.cells.len() != other.cells.len() {
+            return false;
+        }
+        for (i, (row, other_row)) in self.cells.iter().zip(other.cells.iter()).enumerate() {
+            if row.len() != other_row.len() {
+                return false;
+            }
+            for (j, (c, other_c)) in row.iter().zip(other_row.iter()).enumerate() {
+                match (c, other_c) {
+                    (Cell::Crate(_), Cell::Crate(_)) => {
+                        for next in [(i, j+1), (i+1, j)] {
+                            if self.same_crate((i,j), next) != other.same_crate((i,j), next) {
+                                return false;
+                            }
+                        }
+                    },
+                    _ if c != other_c => return false,
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
 }
 
 impl Map {
@@ -54,31 +160,92 @@ impl Map {
     fn get_cell_mut(&mut self, pos: (usize, usize)) -> Option<&mut Cell> {
         self.cells.get_mut(pos.0).and_then(|row| row.get_mut(pos.1))
     }
+    fn same_crate(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+        matches!((self.get_cell(a), self.get_cell(b)), (Some(Cell::Crate(x)), Some(Cell::Crate(y))) if x == y)
+    }
+    // all cells of the crate covering pos, empty if there is none
+    fn crate_footprint(&self, pos: (usize, usize)) -> Vec<(usize,usize)> {
+        if !matches!(self.get_cell(pos), Some(Cell::Crate(_))) {
+            return vec![];
+        }
+        let mut footprint = vec![pos];
+        let mut seen = HashSet::from([pos]);
+        let mut to_explore = vec![pos];
+        while let Some((i,j)) = to_explore.pop() {
+            let neighbours = [(i.wrapping_sub(1), j), (i+1, j), (i, j.wrapping_sub(1)), (i, j+1)];
+            for next in neighbours {
+                if self.same_crate((i,j), next) && seen.insert(next) {
+                    footprint.push(next);
+                    to_explore.push(next);
+                }
+            }
+        }
+        footprint
+    }
     fn _repr(&self) -> String {
+        // single row crates are drawn as O, [] or [==], taller ones with a letter
+        let mut tall = HashSet::new();
+        for i in 1..self.cells.len() {
+            for j in 0..self.cells[i].len() {
+                if let Cell::Crate(id) = self.cells[i][j] {
+                    if self.same_crate((i-1,j), (i,j)) {
+                        tall.insert(id);
+                    }
+                }
+            }
+        }
+        // touching crates with the same letter would read back as one, so start
+        // from the id and skip the letters of the neighbours already drawn
+        let mut letters: HashMap<usize, usize> = HashMap::new();
+        let mut ids: Vec<usize> = tall.iter().copied().collect();
+        ids.sort();
+        for id in ids {
+            let mut taken: HashSet<usize> = HashSet::new();
+            for (i, row) in self.cells.iter().enumerate() {
+                for (j, c) in row.iter().enumerate() {
+                    if *c != Cell::Crate(id) {
+                        continue;
+                    }
+                    for next in [(i.wrapping_sub(1), j), (i+1, j), (i, j.wrapping_sub(1)), (i, j+1)] {
+                        if let Some(Cell::Crate(other)) = self.get_cell(next) {
+                            taken.extend(letters.get(other));
+                        }
+                    }
+                }
+            }
+            let letter = (0..26).map(|k| (id + k) % 26).find(|l| !taken.contains(l)).unwrap_or(id % 26);
+            letters.insert(id, letter);
+        }
         let mut res: String = String::new();
         for i in 0..self.cells.len() {
             for j in 0..self.cells[i].len() {
-                if self.bot == (i,j) {
-                    res += "@";
-                } else if self.cells[i][j] == Cell::Wall {
-                    res += "#";
-                } else if self.cells[i][j] == Cell::Box {
-                    res += "O";
-                } else if self.cells[i][j] == Cell::BoxLeft {
-                    res += "[";
-                } else if self.cells[i][j] == Cell::BoxRight {
-                    res += "]";
-                } else {
-                    res += ".";
+                if self.bots.contains(&(i,j)) {
+                    res.push('@');
+                    continue;
                 }
+                res.push(match self.cells[i][j] {
+                    Cell::Wall => '#',
+                    Cell::Empty => '.',
+                    Cell::Crate(id) if tall.contains(&id) => (b'a' + letters[&id] as u8) as char,
+                    Cell::Crate(_) => {
+                        let left = j > 0 && self.same_crate((i,j-1), (i,j));
+                        let right = self.same_crate((i,j+1), (i,j));
+                        match (left, right) {
+                            (false, false) => 'O',
+                            (false, true) => '[',
+                            (true, true) => '=',
+                            (true, false) => ']',
+                        }
+                    }
+                });
             }
-            res += "\n";
+            res.push('\n');
         }
         res
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Direction {
     Up,
     Down,
@@ -86,43 +253,198 @@ enum Direction {
     Right
 }
 
+impl Direction {
+    fn reverse(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MapError {
+    UnknownChar{line: usize, column: usize, c: char},
+    Ragged{line: usize, len: usize, width: usize},
+    OpenBorder{line: usize, column: usize},
+    UnpairedCrate{line: usize, column: usize},
+    RobotCount(usize),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::UnknownChar{line, column, c} => write!(f, "unknown character {:?} at line {}, column {}", c, line+1, column+1),
+            MapError::Ragged{line, len, width} => write!(f, "line {} has {} cells instead of {}", line+1, len, width),
+            MapError::OpenBorder{line, column} => write!(f, "the border at line {}, column {} is not a wall", line+1, column+1),
+            MapError::UnpairedCrate{line, column} => write!(f, "wide box at line {}, column {} is not closed by [ and ]", line+1, column+1),
+            MapError::RobotCount(count) => write!(f, "the map has {} robots", count),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+// parse_map after checking the map only uses known cells, is closed by walls and
+// has well formed wide boxes and a single robot, unless several are allowed
+fn check_map(s: &str, allow_several_robots: bool) -> Result<Map, MapError> {
+    let rows: Vec<Vec<char>> = s.split_terminator('\n').map(|line| line.chars().collect()).collect();
+    let width = rows.first().map_or(0, |row| row.len());
+    let mut robots = 0;
+    for (line, row) in rows.iter().enumerate() {
+        if row.len() != width {
+            return Err(MapError::Ragged{line, len: row.len(), width});
+        }
+        for (column, &c) in row.iter().enumerate() {
+            if !matches!(c, '#' | '.' | '@' | 'O' | '[' | '=' | ']') && !c.is_ascii_lowercase() {
+                return Err(MapError::UnknownChar{line, column, c});
+            }
+            let border = line == 0 || line + 1 == rows.len() || column == 0 || column + 1 == width;
+            if border && c != '#' {
+                return Err(MapError::OpenBorder{line, column});
+            }
+            let before = if column > 0 { row[column-1] } else { '#' };
+            let after = row.get(column+1).copied().unwrap_or('#');
+            let paired = match c {
+                '[' => matches!(after, '=' | ']'),
+                '=' => matches!(before, '[' | '=') && matches!(after, '=' | ']'),
+                ']' => matches!(before, '[' | '='),
+                _ => true
+            };
+            if !paired {
+                return Err(MapError::UnpairedCrate{line, column});
+            }
+            if c == '@' {
+                robots += 1;
+            }
+        }
+    }
+    if robots == 0 || (robots > 1 && !allow_several_robots) {
+        return Err(MapError::RobotCount(robots));
+    }
+    Ok(parse_map(s))
+}
+
+// the map of an input file, several robots being allowed only with one move
+// block for each of them to run in lockstep
+fn check_input(contents: &str) -> Result<Map, MapError> {
+    let scripts = contents.split("\n\n").skip(1).filter(|block| !block.trim().is_empty()).count();
+    check_map(contents.split("\n\n").next().unwrap(), scripts > 1)
+}
+
+// invalid input is reported without a backtrace
+fn or_exit<T>(res: Result<T, MapError>) -> T {
+    res.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// top left corners of the crates that can never move, whatever the robots do.
+// a crate can only go one way if no wall is ahead of it and there is room behind
+// it for whatever pushes it, crates found stuck then count as walls for the others
+fn stuck_crates(map: &Map) -> Vec<(usize,usize)> {
+    let mut footprints: HashMap<usize, Vec<(usize,usize)>> = HashMap::new();
+    let mut fixed: HashSet<(usize,usize)> = HashSet::new();
+    for (i, row) in map.cells.iter().enumerate() {
+        for (j, c) in row.iter().enumerate() {
+            match c {
+                Cell::Wall => { fixed.insert((i,j)); },
+                Cell::Crate(id) => footprints.entry(*id).or_default().push((i,j)),
+                Cell::Empty => {}
+            }
+        }
+    }
+    let size = map.get_size();
+    let is_fixed = |fixed: &HashSet<(usize,usize)>, pos: Option<(usize,usize)>| pos.is_none_or(|p| fixed.contains(&p));
+
+    let mut stuck = vec![];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        footprints.retain(|_, footprint| {
+            let movable = [Direction::Up, Direction::Down, Direction::Left, Direction::Right].iter().any(|&dir| {
+                let outside = |d: Direction| footprint.iter()
+                    .map(move |&pos| get_moved_position(pos, size, d))
+                    .filter(|next| next.is_none_or(|n| !footprint.contains(&n)));
+                outside(dir).all(|ahead| !is_fixed(&fixed, ahead))
+                    && outside(dir.reverse()).any(|behind| !is_fixed(&fixed, behind))
+            });
+            if !movable {
+                fixed.extend(footprint.iter());
+                stuck.push(footprint.iter().fold((usize::MAX, usize::MAX), |c, p| (c.0.min(p.0), c.1.min(p.1))));
+                changed = true;
+            }
+            movable
+        });
+    }
+    stuck.sort();
+    stuck
+}
+
+// boxes are O, [..] or [==..] for wide ones, and connected runs of the same
+// lowercase letter for arbitrary shapes
 fn parse_map(s: &str) -> Map {
-    Map {
-        cells: s.split_terminator('\n')
-            .map(|line| line.chars().map(|c| match c {
+    let rows: Vec<Vec<char>> = s.split_terminator('\n').map(|line| line.chars().collect()).collect();
+    let mut cells: Vec<Vec<Cell>> = rows.iter().map(|row| vec![Cell::Empty; row.len()]).collect();
+    let mut next_id = 0;
+    for (i, row) in rows.iter().enumerate() {
+        for (j, c) in row.iter().enumerate() {
+            cells[i][j] = match c {
                 '#' => Cell::Wall,
-                'O' => Cell::Box,
+                'O' | '[' => {
+                    next_id += 1;
+                    Cell::Crate(next_id - 1)
+                },
+                // continues the crate opened on the left
+                '=' | ']' if j > 0 && matches!(rows[i][j-1], '[' | '=') => cells[i][j-1],
                 _ => Cell::Empty
-            }).collect())
-            .collect(),
-        bot: s
-            .split_terminator('\n')
-            .enumerate()
-            .find_map(|(i, s)| {
-                s.chars()
-                .enumerate()
-                .find_map(|(j, c)| match c {
-                    '@' => Some((i,j)),
-                    _ => None
-                })
-            }).unwrap()
+            };
+        }
+    }
+    // lettered crates, one per connected group of the same letter
+    for (i, row) in rows.iter().enumerate() {
+        for (j, c) in row.iter().enumerate() {
+            if !c.is_ascii_lowercase() || cells[i][j] != Cell::Empty {
+                continue;
+            }
+            cells[i][j] = Cell::Crate(next_id);
+            let mut to_explore = vec![(i,j)];
+            while let Some((ci,cj)) = to_explore.pop() {
+                for (ni,nj) in [(ci.wrapping_sub(1), cj), (ci+1, cj), (ci, cj.wrapping_sub(1)), (ci, cj+1)] {
+                    if rows.get(ni).and_then(|r| r.get(nj)) == Some(c) && cells[ni][nj] == Cell::Empty {
+                        cells[ni][nj] = Cell::Crate(next_id);
+                        to_explore.push((ni,nj));
+                    }
+                }
+            }
+            next_id += 1;
+        }
     }
+    let bots: Vec<(usize,usize)> = rows.iter()
+        .enumerate()
+        .flat_map(|(i, row)| row.iter().enumerate().filter(|(_, c)| **c == '@').map(move |(j, _)| (i,j)))
+        .collect();
+    assert!(!bots.is_empty(), "The map should have a robot");
+    Map { cells, bots }
 }
 
-fn double_map(map: &Map) -> Map {
+// stretches every cell into a width x height block, crates keep their id
+fn scale_map(map: &Map, width: usize, height: usize) -> Map {
     Map {
-        cells: map.cells.iter().map(|row| row.iter().flat_map(|c| {
-            match c {
-                Cell::Box => [Cell::BoxLeft, Cell::BoxRight].iter(),
-                Cell::Wall => [Cell::Wall, Cell::Wall].iter(),
-                Cell::Empty => [Cell::Empty, Cell::Empty].iter(),
-                _ => [].iter(),
-            }
-        }).cloned().collect()).collect(),
-        bot: (map.bot.0, map.bot.1*2)
+        cells: map.cells.iter().flat_map(|row| {
+            let scaled: Vec<Cell> = row.iter().flat_map(|c| std::iter::repeat_n(*c, width)).collect();
+            std::iter::repeat_n(scaled, height)
+        }).collect(),
+        bots: map.bots.iter().map(|bot| (bot.0*height, bot.1*width)).collect()
     }
 }
 
+fn double_map(map: &Map) -> Map {
+    scale_map(map, 2, 1)
+}
 
 fn parse_directions(s: &str) -> Vec<Direction> {
     s.chars().filter_map(|c| match c {
@@ -167,70 +489,422 @@ fn get_moved_position(pos: (usize, usize), size: (usize, usize), dir: Direction)
     }
 }
 
-fn get_pushed_boxes(map: &Map, dir: Direction) -> Option<Vec<(usize,usize)>>{
+fn get_pushed_boxes(map: &Map, robot: usize, dir: Direction) -> Option<Vec<(usize,usize)>>{
     // returns None if boxes can't be pushed, else the list of cells containing pushed boxes
     let mut boxes = vec![];
-    
-    let mut to_explore = vec![map.bot];
-    while !to_explore.is_empty() {
-        //println!("Discover {:?}", to_explore);
-        let mut joined_to_explore = HashSet::new();
-        for e in to_explore {
-            joined_to_explore.insert(e);
-            if dir == Direction::Up || dir == Direction::Down {
-                if *map.get_cell(e).unwrap() == Cell::BoxLeft {
-                    joined_to_explore.insert(get_moved_position(e, map.get_size(), Direction::Right).unwrap());
-                } else if *map.get_cell(e).unwrap() == Cell::BoxRight {
-                    joined_to_explore.insert(get_moved_position(e, map.get_size(), Direction::Left).unwrap());
+    let mut seen = HashSet::new();
+    let mut crates = HashSet::new();
+
+    let mut to_explore = vec![map.bots[robot]];
+    while let Some(e) = to_explore.pop() {
+        if !seen.insert(e) {
+            continue;
+        }
+        boxes.push(e);
+        // a crate moves as a whole
+        if let Some(Cell::Crate(id)) = map.get_cell(e) {
+            if crates.insert(*id) {
+                to_explore.extend(map.crate_footprint(e));
+            }
+        }
+        let next = get_moved_position(e, map.get_size(), dir).unwrap();
+        // robots hold their ground, only boxes get pushed; the pushing robot
+        // itself leaves its cell in the same step
+        if next != map.bots[robot] && map.bots.contains(&next) {
+            return None;
+        }
+        match *map.get_cell(next).unwrap() {
+            Cell::Wall => return None,
+            Cell::Empty => {},
+            Cell::Crate(_) => to_explore.push(next)
+        }
+    }
+
+    Some(boxes)
+}
+
+// returns the moved cells with their position before the move, or None if blocked
+fn try_move(map: &mut Map, dir: Direction) -> Option<Vec<((usize,usize), Cell)>> {
+    try_move_robot(map, 0, dir)
+}
+
+fn try_move_robot(map: &mut Map, robot: usize, dir: Direction) -> Option<Vec<((usize,usize), Cell)>> {
+    let boxes = get_pushed_boxes(map, robot, dir)?;
+    Some(apply_push(map, robot, dir, &boxes))
+}
+
+fn apply_push(map: &mut Map, robot: usize, dir: Direction, boxes: &[(usize,usize)]) -> Vec<((usize,usize), Cell)> {
+    let mut moved_boxes: Vec<((usize,usize), Cell)> = vec![];
+    // erase boxes
+    for pos in boxes.iter() {
+        let cell_ref = map.get_cell_mut(*pos).unwrap();
+        moved_boxes.push(((*pos), *cell_ref));
+        *cell_ref = Cell::Empty;
+    }
+    // put back moved boxes
+    for (pos, cell) in moved_boxes.iter() {
+        let target = get_moved_position(*pos, map.get_size(), dir).unwrap();
+        *map.get_cell_mut(target).unwrap() = *cell;
+    } 
+    map.bots[robot] = get_moved_position(map.bots[robot], map.get_size(), dir).unwrap();
+    moved_boxes
+}
+
+// moves every robot with a move this round at once and returns which ones moved.
+// pushes are planned on the map as it was at the start of the round, each robot
+// claims the cells its push leaves or enters, and a robot whose claim overlaps the
+// claim of a lower numbered robot is held back for the round
+fn lockstep_round(map: &mut Map, moves: &[Option<Direction>]) -> Vec<bool> {
+    let mut claimed: HashSet<(usize,usize)> = HashSet::new();
+    let mut accepted = vec![];
+    let mut moved = vec![false; map.bots.len()];
+    for (robot, dir) in moves.iter().enumerate() {
+        let Some(dir) = *dir else { continue };
+        let Some(boxes) = get_pushed_boxes(map, robot, dir) else { continue };
+        let claim: Vec<(usize,usize)> = boxes.iter()
+            .flat_map(|pos| [*pos, get_moved_position(*pos, map.get_size(), dir).unwrap()])
+            .collect();
+        if claim.iter().any(|pos| claimed.contains(pos)) {
+            continue;
+        }
+        claimed.extend(claim);
+        accepted.push((robot, dir, boxes));
+        moved[robot] = true;
+    }
+    // claims are disjoint, so applying them one after the other is the same as at once
+    for (robot, dir, boxes) in accepted {
+        apply_push(map, robot, dir, &boxes);
+    }
+    moved
+}
+
+// runs one move script per robot until all of them are exhausted, returns the round count
+fn run_lockstep(map: &mut Map, scripts: &[Vec<Direction>]) -> usize {
+    let rounds = scripts.iter().map(|s| s.len()).max().unwrap_or(0);
+    for round in 0..rounds {
+        let moves: Vec<Option<Direction>> = (0..map.bots.len())
+            .map(|robot| scripts.get(robot).and_then(|s| s.get(round)).copied())
+            .collect();
+        lockstep_round(map, &moves);
+    }
+    rounds
+}
+
+fn get_checksum(map: &Map) -> usize {
+    // a crate counts once, from the top row and left column of its footprint
+    let mut corners: HashMap<usize, (usize,usize)> = HashMap::new();
+    for (i, row) in map.cells.iter().enumerate() {
+        for (j, c) in row.iter().enumerate() {
+            if let Cell::Crate(id) = c {
+                let corner = corners.entry(*id).or_insert((i,j));
+                *corner = (corner.0.min(i), corner.1.min(j));
+            }
+        }
+    }
+    corners.values().map(|(i,j)| i*100+j).sum()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Change {
+    pos: (usize,usize),
+    before: Cell,
+    after: Cell
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Step {
+    dir: Direction,
+    bot_before: (usize,usize),
+    bot_after: (usize,usize),
+    changes: Vec<Change>
+}
+
+// a move sequence stored as the start map plus the cells each move changed
+struct Replay {
+    start: Map,
+    steps: Vec<Step>
+}
+
+impl Replay {
+    fn record(map: &Map, moves: &[Direction]) -> Replay {
+        let mut current = map.clone();
+        let mut steps = vec![];
+        for &dir in moves {
+            let bot_before = current.bots[0];
+            let moved = try_move(&mut current, dir).unwrap_or_default();
+            // a push only moves cells into empty ones, so the targets that weren't
+            // an origin were empty before the move
+            let mut positions: Vec<(usize,usize)> = moved.iter().map(|(pos,_)| *pos).collect();
+            for (pos, _) in moved.iter() {
+                let target = get_moved_position(*pos, current.get_size(), dir).unwrap();
+                if !positions.contains(&target) {
+                    positions.push(target);
                 }
             }
+            let changes = positions.into_iter()
+                .map(|pos| Change {
+                    pos,
+                    before: moved.iter().find(|(p,_)| *p == pos).map_or(Cell::Empty, |(_,c)| *c),
+                    after: *current.get_cell(pos).unwrap()
+                })
+                .filter(|c| c.before != c.after)
+                .collect();
+            steps.push(Step { dir, bot_before, bot_after: current.bots[0], changes });
+        }
+        Replay { start: map.clone(), steps }
+    }
+
+    fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    // map after the first n moves
+    fn state_at(&self, n: usize) -> Map {
+        let mut cursor = ReplayCursor::new(self);
+        cursor.seek(n);
+        cursor.map
+    }
+
+    fn text_animation(&self) -> String {
+        let mut cursor = ReplayCursor::new(self);
+        let mut res = format!("Start:\n{}\n", cursor.map._repr());
+        while let Some(step) = cursor.step_forward() {
+            let dir = match step.dir {
+                Direction::Up => '^',
+                Direction::Down => 'v',
+                Direction::Left => '<',
+                Direction::Right => '>',
+            };
+            res += &format!("Move {} {}:\n{}\n", cursor.index, dir, cursor.map._repr());
+        }
+        res
+    }
+
+    // writes one PGM image per state, numbered from the start map
+    fn export_frames(&self, dir: &Path) -> io::Result<usize> {
+        fs::create_dir_all(dir)?;
+        let digits = self.len().to_string().len();
+        let mut cursor = ReplayCursor::new(self);
+        loop {
+            let name = format!("frame_{:0width$}.pgm", cursor.index, width = digits);
+            fs::write(dir.join(name), to_pgm(&cursor.map))?;
+            if cursor.step_forward().is_none() {
+                break;
+            }
         }
+        Ok(self.len() + 1)
+    }
+}
+
+// walks a replay in either direction by applying or reverting the recorded changes
+struct ReplayCursor<'a> {
+    replay: &'a Replay,
+    map: Map,
+    index: usize
+}
 
-        let mut new_to_explore = vec![];
-        for expl in joined_to_explore {
-            boxes.push(expl);
-            let next = get_moved_position(expl, map.get_size(), dir).unwrap();
-            if *map.get_cell(next).unwrap() == Cell::Wall {
-                return None;
-            } else if *map.get_cell(next).unwrap() == Cell::Empty {
+impl<'a> ReplayCursor<'a> {
+    fn new(replay: &'a Replay) -> ReplayCursor<'a> {
+        ReplayCursor { replay, map: replay.start.clone(), index: 0 }
+    }
+
+    fn step_forward(&mut self) -> Option<&'a Step> {
+        let step = self.replay.steps.get(self.index)?;
+        for change in step.changes.iter() {
+            *self.map.get_cell_mut(change.pos).unwrap() = change.after;
+        }
+        self.map.bots[0] = step.bot_after;
+        self.index += 1;
+        Some(step)
+    }
+
+    fn step_back(&mut self) -> Option<&'a Step> {
+        self.index = self.index.checked_sub(1)?;
+        let step = &self.replay.steps[self.index];
+        for change in step.changes.iter() {
+            *self.map.get_cell_mut(change.pos).unwrap() = change.before;
+        }
+        self.map.bots[0] = step.bot_before;
+        Some(step)
+    }
+
+    // clamps to the end of the replay
+    fn seek(&mut self, n: usize) {
+        while self.index < n && self.step_forward().is_some() {}
+        while self.index > n && self.step_back().is_some() {}
+    }
+}
+
+// number of moves after which the two runs first show different maps,
+// comparing only the moves both runs have
+fn first_divergence(a: &Replay, b: &Replay) -> Option<usize> {
+    let mut cursor_a = ReplayCursor::new(a);
+    let mut cursor_b = ReplayCursor::new(b);
+    loop {
+        if cursor_a.map != cursor_b.map {
+            return Some(cursor_a.index);
+        }
+        if cursor_a.step_forward().is_none() || cursor_b.step_forward().is_none() {
+            return None;
+        }
+    }
+}
+
+// greyscale image, one pixel per cell: walls black, boxes grey, robot white
+fn to_pgm(map: &Map) -> String {
+    let (height, width) = map.get_size();
+    let mut res = format!("P2\n{} {}\n3\n", width, height);
+    for (i, row) in map.cells.iter().enumerate() {
+        let values: Vec<&str> = row.iter().enumerate().map(|(j, c)| {
+            if map.bots.contains(&(i,j)) {
+                "3"
             } else {
-                new_to_explore.push(next);
+                match c {
+                    Cell::Wall => "0",
+                    Cell::Empty => "2",
+                    _ => "1",
+                }
+            }
+        }).collect();
+        res += &values.join(" ");
+        res += "\n";
+    }
+    res
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Key {
+    Move(Direction),
+    Undo,
+    Redo,
+    Quit
+}
+
+fn parse_key(c: char) -> Option<Key> {
+    match c {
+        '^' | 'w' => Some(Key::Move(Direction::Up)),
+        'v' | 's' => Some(Key::Move(Direction::Down)),
+        '<' | 'a' => Some(Key::Move(Direction::Left)),
+        '>' | 'd' => Some(Key::Move(Direction::Right)),
+        'u' => Some(Key::Undo),
+        'r' => Some(Key::Redo),
+        // ctrl-c and ctrl-d arrive as plain bytes since signals are off while playing
+        'q' | '\x03' | '\x04' => Some(Key::Quit),
+        _ => None
+    }
+}
+
+// turns terminal bytes into keys, arrow keys being sent as ESC [ A/B/C/D
+#[derive(Default)]
+struct KeyDecoder {
+    // bytes of an escape sequence seen so far
+    escape: usize
+}
+
+impl KeyDecoder {
+    fn feed(&mut self, byte: u8) -> Option<Key> {
+        match (self.escape, byte) {
+            (0, 0x1b) | (1, b'[') => {
+                self.escape += 1;
+                None
+            },
+            (2, _) => {
+                self.escape = 0;
+                match byte {
+                    b'A' => Some(Key::Move(Direction::Up)),
+                    b'B' => Some(Key::Move(Direction::Down)),
+                    b'C' => Some(Key::Move(Direction::Right)),
+                    b'D' => Some(Key::Move(Direction::Left)),
+                    _ => None
+                }
+            },
+            _ => {
+                self.escape = 0;
+                parse_key(byte as char)
             }
         }
-        to_explore = new_to_explore;
     }
+}
 
-    Some(boxes)
+struct Session {
+    map: Map,
+    undo: Vec<Map>,
+    redo: Vec<Map>
 }
 
-fn try_move(map: &mut Map, dir: Direction) {
-    if let Some(boxes) = get_pushed_boxes(map, dir) {
-        let mut moved_boxes: HashSet<((usize,usize), Cell)> = HashSet::new();
-        // erase boxes
-        for pos in boxes.iter() {
-            let cell_ref = map.get_cell_mut(*pos).unwrap();
-            moved_boxes.insert(((*pos), *cell_ref));
-            *cell_ref = Cell::Empty;
+impl Session {
+    fn new(map: Map) -> Session {
+        Session { map, undo: vec![], redo: vec![] }
+    }
+
+    // returns false once the player wants to quit
+    fn apply(&mut self, key: Key) -> bool {
+        match key {
+            Key::Move(dir) => {
+                let before = self.map.clone();
+                try_move(&mut self.map, dir);
+                // blocked moves don't clutter the history
+                if self.map != before {
+                    self.undo.push(before);
+                    self.redo.clear();
+                }
+            },
+            Key::Undo => {
+                if let Some(prev) = self.undo.pop() {
+                    self.redo.push(std::mem::replace(&mut self.map, prev));
+                }
+            },
+            Key::Redo => {
+                if let Some(next) = self.redo.pop() {
+                    self.undo.push(std::mem::replace(&mut self.map, next));
+                }
+            },
+            Key::Quit => return false
         }
-        // put back moved boxes
-        for (pos, cell) in moved_boxes {
-            let target = get_moved_position(pos, map.get_size(), dir).unwrap();
-            *map.get_cell_mut(target).unwrap() = cell;
-        } 
-        map.bot = get_moved_position(map.bot, map.get_size(), dir).unwrap();
+        true
+    }
+
+    fn render(&self) -> String {
+        // clear the screen and draw from the top left corner
+        format!("\x1b[2J\x1b[H{}\nGPS checksum: {}\nMoves: {} (redo {})\n^v<>/WASD move, u undo, r redo, q quit\n",
+            self.map._repr(), get_checksum(&self.map), self.undo.len(), self.redo.len())
     }
 }
 
-fn get_checksum(map: &Map) -> usize {
-    map.cells.iter()
-        .enumerate()
-        .flat_map(|(i,row)|
-            row.iter()
-                .enumerate()
-                .filter(|(_,c)| **c == Cell::Box || **c == Cell::BoxLeft)
-                .map(move |(j,_)| (i,j))
-        ).map(|(i,j)| i*100+j)
-        .sum()
+fn play<R: BufRead, W: Write>(map: Map, input: R, mut output: W) -> io::Result<Map> {
+    let mut session = Session::new(map);
+    output.write_all(session.render().as_bytes())?;
+    output.flush()?;
+    let mut decoder = KeyDecoder::default();
+    for byte in input.bytes() {
+        let Some(key) = decoder.feed(byte?) else { continue };
+        if !session.apply(key) {
+            break;
+        }
+        output.write_all(session.render().as_bytes())?;
+        output.flush()?;
+    }
+    Ok(session.map)
+}
+
+// puts the terminal in unbuffered, no-echo mode and restores it on drop; signal
+// keys are turned off too, so ctrl-c quits through the drop instead of killing us
+struct RawTerminal {
+    saved: String
+}
+
+impl RawTerminal {
+    fn enable() -> io::Result<RawTerminal> {
+        let saved = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output()?;
+        Command::new("stty").args(["-icanon", "-echo", "-isig", "min", "1"]).stdin(Stdio::inherit()).status()?;
+        Ok(RawTerminal { saved: String::from_utf8_lossy(&saved.stdout).trim().to_string() })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).stdin(Stdio::inherit()).status();
+    }
 }
 
 #[cfg(test)]
@@ -239,15 +913,15 @@ mod tests {
 
     #[test]
     fn example_small() {
-        let mut map = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
-        let moves = parse_directions("<^^>>>vv<v>>v<<");
+        let start = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
+        let replay = Replay::record(&start, &parse_directions("<^^>>>vv<v>>v<<"));
 
-        println!("{}\n", map._repr());
-        for m in moves {
-            try_move(&mut map, m);
-            println!("{}\n", map._repr());
-        }
+        // the first move is blocked, the fourth pushes a box
+        assert_eq!(replay.state_at(1), start);
+        assert_eq!(replay.state_at(2)._repr().lines().nth(1), Some("#.@O.O.#"));
+        assert_eq!(replay.state_at(4)._repr().lines().nth(1), Some("#..@OO.#"));
 
+        let map = replay.state_at(replay.len());
         let target_map = parse_map("########\n#....OO#\n##.....#\n#.....O#\n#.#O@..#\n#...O..#\n#...O..#\n########");
         assert_eq!(map, target_map);
 
@@ -256,30 +930,234 @@ mod tests {
 
     #[test]
     fn example_big() {
-        let mut map = parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########");
+        let start = parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########");
         let moves = parse_directions("<vv>^<v^>v>^vv^v>v<>v^v<v<^vv<<<^><<><>>v<vvv<>^v^>^<<<><<v<<<v^vv^v>^vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v><>vv>v^v^<>><>>>><^^>vv>v<^^^>>v^v^<^^>v^^>v^<^v>v<>>v^v^<v>v^^<^^vv<<<v<^>>^^^^>>>v^<>vvv^><v<<<>^^^vv^<vvv>^>v<^^^^v<>^>vvvv><>>v^<<^^^^^^><^><>>><>^^<<^^v>>><^<v>^<vv>>v>>>^v><>^v><<<<v>>v<v<v>vvv>^<><<>^><^>><>^v<><^vvv<^^<><v<<<<<><^v<<<><<<^^<v<^^^><^>>^<v^><<<^>>^v<v^v<v^>^>>^v>vv>^<<^v<>><<><<v<<v><>v<^vv<<<>^^v^>^^>>><<^v>>v^v><^^>>^<>vv^<><^^>^^^<><vvvvv^v<v<<>^v<v>v<<^><<><<><<<^^<<<^<<>><<><^^^>^^<>^>v<>^^>vv<^v^v<vv>^<><v<^v>^^^>>>^^vvv^>vvv<>>>^<^>>>>>^<<^v>^vvv<>^<><<v>v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^");
 
         let target_map = parse_map("##########\n#.O.O.OOO#\n#........#\n#OO......#\n#OO@.....#\n#O#.....O#\n#O.....OO#\n#O.....OO#\n#OO....OO#\n##########");
 
-        for m in moves.clone() {
+        let replay = Replay::record(&start, &moves);
+        assert_eq!(replay.state_at(moves.len()), target_map);
+        assert_eq!(get_checksum(&target_map), 10092);
+    }
+
+    #[test]
+    fn example_big_doubled() {
+        let start = double_map(&parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########"));
+        let moves = parse_directions("<vv>^<v^>v>^vv^v>v<>v^v<v<^vv<<<^><<><>>v<vvv<>^v^>^<<<><<v<<<v^vv^v>^vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v><>vv>v^v^<>><>>>><^^>vv>v<^^^>>v^v^<^^>v^^>v^<^v>v<>>v^v^<v>v^^<^^vv<<<v<^>>^^^^>>>v^<>vvv^><v<<<>^^^vv^<vvv>^>v<^^^^v<>^>vvvv><>>v^<<^^^^^^><^><>>><>^^<<^^v>>><^<v>^<vv>>v>>>^v><>^v><<<<v>>v<v<v>vvv>^<><<>^><^>><>^v<><^vvv<^^<><v<<<<<><^v<<<><<<^^<v<^^^><^>>^<v^><<<^>>^v<v^v<v^>^>>^v>vv>^<<^v<>><<><<v<<v><>v<^vv<<<>^^v^>^^>>><<^v>>v^v><^^>>^<>vv^<><^^>^^^<><vvvvv^v<v<<>^v<v>v<<^><<><<><<<^^<<<^<<>><<><^^^>^^<>^>v<>^^>vv<^v^v<vv>^<><v<^v>^^^>>>^^vvv^>vvv<>>>^<^>>>>>^<<^v>^vvv<>^<><<v>v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^");
+
+        let replay = Replay::record(&start, &moves);
+        let map = replay.state_at(moves.len());
+        assert_eq!(map._repr(), "####################\n##[].......[].[][]##\n##[]...........[].##\n##[]........[][][]##\n##[]......[]....[]##\n##..##......[]....##\n##..[]............##\n##..@......[].[][]##\n##......[][]..[]..##\n####################\n");
+        assert_eq!(get_checksum(&map), 9021);
+    }
+
+    #[test]
+    fn play_undo_redo() {
+        let start = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
+        let target_map = parse_map("########\n#....OO#\n##.....#\n#.....O#\n#.#O@..#\n#...O..#\n#...O..#\n########");
+
+        // same moves as example_small, typed with a mix of ^v<>, WASD and arrow keys
+        let mut output = vec![];
+        let end = play(start.clone(), "<w\x1b[A>d\x1b[Csv<v>\x1b[Cv\x1b[Da".as_bytes(), &mut output).unwrap();
+        assert_eq!(end, target_map);
+        assert!(String::from_utf8(output).unwrap().contains("GPS checksum: 2028"));
+
+        // undo everything, redo it all, and stop at quit
+        let end = play(start.clone(), "<^^>>>vv<v>>v<<uuuuuuuuuuuuuuuuuuuuuuu".as_bytes(), io::sink()).unwrap();
+        assert_eq!(end, start);
+        let end = play(start.clone(), "<^^>>>vv<v>>v<<uuuuuuuuuuuuuuuuuuuuurrrrrrrrrrrrrrrrrrrrrq<<<".as_bytes(), io::sink()).unwrap();
+        assert_eq!(end, target_map);
+
+        // a new move after undo drops the redo history
+        let mut session = Session::new(start.clone());
+        session.apply(Key::Move(Direction::Right));
+        session.apply(Key::Undo);
+        session.apply(Key::Move(Direction::Down));
+        session.apply(Key::Redo);
+        assert!(session.redo.is_empty());
+        assert_eq!(session.map.bots, vec![(3,2)]);
+
+        let mut decoder = KeyDecoder::default();
+        let keys: Vec<Option<Key>> = "\x1b[A\x1b[DADq\x03".bytes().map(|b| decoder.feed(b)).collect();
+        assert_eq!(keys, vec![None, None, Some(Key::Move(Direction::Up)), None, None, Some(Key::Move(Direction::Left)),
+            None, None, Some(Key::Quit), Some(Key::Quit)]);
+    }
+
+    #[test]
+    fn play_double() {
+        let mut map = double_map(&parse_map("#######\n#...#.#\n#.....#\n#..OO@#\n#..O..#\n#.....#\n#######"));
+        let end = play(map.clone(), "<vv<<^^<<^^".as_bytes(), io::sink()).unwrap();
+        for m in parse_directions("<vv<<^^<<^^") {
             try_move(&mut map, m);
-            println!("{}\n", map._repr());
         }
-        
-        assert_eq!(map, target_map);
+        assert_eq!(end, map);
+        assert_eq!(get_checksum(&end), 105 + 207 + 306);
     }
 
     #[test]
-    fn example_big_doubled() {
-        let mut map = parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########");
-        map = double_map(&map);
+    fn replay_seek() {
+        let start = double_map(&parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########"));
         let moves = parse_directions("<vv>^<v^>v>^vv^v>v<>v^v<v<^vv<<<^><<><>>v<vvv<>^v^>^<<<><<v<<<v^vv^v>^vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v><>vv>v^v^<>><>>>><^^>vv>v<^^^>>v^v^<^^>v^^>v^<^v>v<>>v^v^<v>v^^<^^vv<<<v<^>>^^^^>>>v^<>vvv^><v<<<>^^^vv^<vvv>^>v<^^^^v<>^>vvvv><>>v^<<^^^^^^><^><>>><>^^<<^^v>>><^<v>^<vv>>v>>>^v><>^v><<<<v>>v<v<v>vvv>^<><<>^><^>><>^v<><^vvv<^^<><v<<<<<><^v<<<><<<^^<v<^^^><^>>^<v^><<<^>>^v<v^v<v^>^>>^v>vv>^<<^v<>><<><<v<<v><>v<^vv<<<>^^v^>^^>>><<^v>>v^v><^^>>^<>vv^<><^^>^^^<><vvvvv^v<v<<>^v<v>v<<^><<><<><<<^^<<<^<<>><<><^^^>^^<>^>v<>^^>vv<^v^v<vv>^<><v<^v>^^^>>>^^vvv^>vvv<>>>^<^>>>>>^<<^v>^vvv<>^<><<v>v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^");
+        let replay = Replay::record(&start, &moves);
+        assert_eq!(replay.len(), moves.len());
+        assert_eq!(get_checksum(&replay.state_at(moves.len())), 9021);
+
+        // every state matches a fresh simulation, seeking forwards and backwards
+        let mut expected = vec![start.clone()];
+        let mut map = start.clone();
+        for m in moves.iter() {
+            try_move(&mut map, *m);
+            expected.push(map.clone());
+        }
+        let mut cursor = ReplayCursor::new(&replay);
+        for n in [700, 3, 0, 699, 350, 351, 10000, 12] {
+            cursor.seek(n);
+            assert_eq!(cursor.map, expected[n.min(moves.len())]);
+        }
+        assert_eq!(replay.state_at(0), start);
+    }
+
+    #[test]
+    fn replay_divergence() {
+        let start = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
+        let a = Replay::record(&start, &parse_directions("<^^>>>vv<v>>v<<"));
+        let b = Replay::record(&start, &parse_directions("<^^>>>vv<^>>v<<"));
+        // a blocked move leaves the state alone, so only the robot moving down differs
+        let c = Replay::record(&start, &parse_directions("v^^>>>"));
+        assert_eq!(first_divergence(&a, &a), None);
+        assert_eq!(first_divergence(&a, &b), Some(10));
+        assert_eq!(first_divergence(&a, &c), Some(1));
+        assert_eq!(first_divergence(&a, &Replay::record(&start, &[])), None);
 
-        for m in moves.clone() {
+        let animation = a.text_animation();
+        assert!(animation.starts_with("Start:\n########\n"));
+        assert!(animation.contains("Move 15 <:\n########\n#....OO#\n##.....#\n#.....O#\n#.#O@..#\n"));
+        assert_eq!(to_pgm(&start).lines().nth(4), Some("0 2 2 1 2 1 2 0"));
+    }
+
+    #[test]
+    fn scaled_maps() {
+        let small = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
+        let big = parse_map("##########\n#..O..O.O#\n#......O.#\n#.OO..O.O#\n#..O@..O.#\n#O#..O...#\n#O..O..O.#\n#.OO.O.OO#\n#....O...#\n##########");
+        let moves = parse_directions("<vv>^<v^>v>^vv^v>v<>v^v<v<^vv<<<^><<><>>v<vvv<>^v^>^<<<><<v<<<v^vv^v>^vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v><>vv>v^v^<>><>>>><^^>vv>v<^^^>>v^v^<^^>v^^>v^<^v>v<>>v^v^<v>v^^<^^vv<<<v<^>>^^^^>>>v^<>vvv^><v<<<>^^^vv^<vvv>^>v<^^^^v<>^>vvvv><>>v^<<^^^^^^><^><>>><>^^<<^^v>>><^<v>^<vv>>v>>>^v><>^v><<<<v>>v<v<v>vvv>^<><<>^><^>><>^v<><^vvv<^^<><v<<<<<><^v<<<><<<^^<v<^^^><^>>^<v^><<<^>>^v<v^v<v^>^>>^v>vv>^<<^v<>><<><<v<<v><>v<^vv<<<>^^v^>^^>>><<^v>>v^v><^^>>^<>vv^<><^^>^^^<><vvvvv^v<v<<>^v<v>v<<^><<><<><<<^^<<<^<<>><<><^^^>^^<>^>v<>^^>vv<^v^v<vv>^<><v<^v>^^^>>>^^vvv^>vvv<>>>^<^>>>>>^<<^v>^vvv<>^<><<v>v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^");
+
+        let mut map = scale_map(&small, 1, 1);
+        assert_eq!(map, small);
+        for m in parse_directions("<^^>>>vv<v>>v<<") {
             try_move(&mut map, m);
-            println!("{}\n", map._repr());
         }
-        
-        assert_eq!(get_checksum(&map), 9021);
+        assert_eq!(get_checksum(&map), 2028);
+
+        let mut map = scale_map(&big, 1, 1);
+        let mut wide = scale_map(&big, 2, 1);
+        assert_eq!(wide, double_map(&big));
+        assert_eq!(wide._repr().lines().nth(1), Some("##....[]....[]..[]##"));
+        for m in moves.iter() {
+            try_move(&mut map, *m);
+            try_move(&mut wide, *m);
+        }
+        assert_eq!(get_checksum(&map), 10092);
+        assert_eq!(get_checksum(&wide), 9021);
+
+        let tall = scale_map(&small, 3, 2);
+        assert_eq!(tall.bots, vec![(4, 6)]);
+        assert_eq!(tall._repr().lines().nth(2), Some("###......aaa...bbb...###"));
+    }
+
+    #[test]
+    fn shaped_crates() {
+        // a wide crate lifts the two boxes resting on it
+        let mut map = parse_map("#######\n#.....#\n#.O.O.#\n#.[=].#\n#..@..#\n#######");
+        for m in parse_directions("^^") {
+            try_move(&mut map, m);
+        }
+        assert_eq!(map, parse_map("#######\n#.O.O.#\n#.[=].#\n#..@..#\n#.....#\n#######"));
+        assert_eq!(get_checksum(&map), 102 + 104 + 202);
+
+        // L-shaped crates move as a whole, overhangs included
+        let mut map = parse_map("#########\n#.......#\n#..aa...#\n#..a.bb.#\n#..@.b..#\n#.......#\n#########");
+        for m in parse_directions("^>>^<<^") {
+            try_move(&mut map, m);
+        }
+        assert_eq!(map, parse_map("#########\n#.aa....#\n#.a@....#\n#.....bb#\n#.....b.#\n#.......#\n#########"));
+        assert_eq!(get_checksum(&map), 102 + 306);
+        assert_eq!(map._repr(), "#########\n#.aa....#\n#.a@....#\n#.....bb#\n#.....b.#\n#.......#\n#########\n");
+
+        // a crate hooked around the robot is pushed from the inside
+        let mut map = parse_map("#######\n#.....#\n#.aa..#\n#.@a..#\n#.aa..#\n#.....#\n#######");
+        assert!(try_move(&mut map, Direction::Up).is_some());
+        assert_eq!(map, parse_map("#######\n#.aa..#\n#.@a..#\n#.aa..#\n#.....#\n#.....#\n#######"));
+
+        // touching crates whose ids share a letter still read back as two
+        let mut map = parse_map("######\n#....#\n#....#\n#@...#\n######");
+        for (pos, id) in [((1, 2), 0), ((2, 2), 0), ((1, 3), 26), ((2, 3), 26)] {
+            map.cells[pos.0][pos.1] = Cell::Crate(id);
+        }
+        assert_eq!(map._repr(), "######\n#.ab.#\n#.ab.#\n#@...#\n######\n");
+        assert_eq!(parse_map(&map._repr()), map);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lockstep_robots() {
+        // a single robot in lockstep is the same as plain moves
+        let start = parse_map("########\n#..O.O.#\n##@.O..#\n#...O..#\n#.#.O..#\n#...O..#\n#......#\n########");
+        let mut map = start.clone();
+        assert_eq!(run_lockstep(&mut map, &[parse_directions("<^^>>>vv<v>>v<<")]), 15);
+        assert_eq!(get_checksum(&map), 2028);
+
+        // robots block each other and the boxes pushed into them
+        let mut map = parse_map("#######\n#@.O.@#\n#######");
+        assert_eq!(lockstep_round(&mut map, &[Some(Direction::Right), Some(Direction::Left)]), vec![true, true]);
+        assert_eq!(lockstep_round(&mut map, &[Some(Direction::Right), Some(Direction::Left)]), vec![false, false]);
+        assert_eq!(map._repr(), "#######\n#.@O@.#\n#######\n");
+
+        // both want the same empty cell, the first robot wins
+        let mut map = parse_map("#########\n#@.O..@.#\n#########");
+        run_lockstep(&mut map, &[parse_directions(">>"), parse_directions("<<")]);
+        assert_eq!(map._repr(), "#########\n#..@O@..#\n#########\n");
+
+        // two robots pushing the same crate, only the first one gets to
+        let mut map = parse_map("#######\n#.....#\n#.[]..#\n#.@@..#\n#######");
+        assert_eq!(lockstep_round(&mut map, &[Some(Direction::Up), Some(Direction::Up)]), vec![true, false]);
+        assert_eq!(map, parse_map("#######\n#.[]..#\n#.@...#\n#..@..#\n#######"));
+
+        // a robot shoves a box into the other one's path and stops it
+        let mut map = parse_map("#######\n#.@...#\n#.O...#\n#.....#\n##...@#\n#######");
+        run_lockstep(&mut map, &[parse_directions("vv"), parse_directions("<<<<")]);
+        assert_eq!(map._repr(), "#######\n#.....#\n#.....#\n#.@...#\n##O@..#\n#######\n");
+    }
+
+    #[test]
+    fn map_validation() {
+        assert!(check_map("########\n#..O.O.#\n##@.O..#\n#.[]a..#\n#.#.aa.#\n########", false).is_ok());
+        assert_eq!(check_map("######\n#.@.X#\n######", false), Err(MapError::UnknownChar{line: 1, column: 4, c: 'X'}));
+        assert_eq!(check_map("######\n#.@..#\n#....\n######", false), Err(MapError::Ragged{line: 2, len: 5, width: 6}));
+        assert_eq!(check_map("######\n#.@...\n######", false), Err(MapError::OpenBorder{line: 1, column: 5}));
+        assert_eq!(check_map("######\n#.@..#\n###.##", false), Err(MapError::OpenBorder{line: 2, column: 3}));
+        assert_eq!(check_map("######\n#@[..#\n######", false), Err(MapError::UnpairedCrate{line: 1, column: 2}));
+        assert_eq!(check_map("######\n#@.].#\n######", false), Err(MapError::UnpairedCrate{line: 1, column: 3}));
+        assert_eq!(check_map("######\n#@[=.#\n######", false), Err(MapError::UnpairedCrate{line: 1, column: 3}));
+        assert_eq!(check_map("######\n#....#\n######", false), Err(MapError::RobotCount(0)));
+        assert_eq!(check_map("######\n#@..@#\n######", false), Err(MapError::RobotCount(2)));
+        assert_eq!(check_map("######\n#@..@#\n######", true).unwrap().bots, vec![(1,1), (1,4)]);
+
+        // input files only get several robots with a move block for each
+        assert_eq!(check_input("######\n#@..@#\n######\n\n>>\n"), Err(MapError::RobotCount(2)));
+        assert_eq!(check_input("######\n#@..@#\n######\n\n>>\n\n<<\n").unwrap().bots, vec![(1,1), (1,4)]);
+        assert!(check_input("######\n#@...#\n######\n\n>>\n").is_ok());
+    }
+
+    #[test]
+    fn stuck_boxes() {
+        let map = parse_map("#######\n#O...O#\n#..O..#\n#O....#\n#O.@..#\n#######");
+        // the box above the bottom left corner can only leave through the corner
+        assert_eq!(stuck_crates(&map), vec![(1,1), (1,5), (3,1), (4,1)]);
+        assert_eq!(stuck_crates(&double_map(&map)), vec![(1,2), (1,10), (3,2), (4,2)]);
+
+        // a crate in a corner is stuck as a whole, not cell by cell
+        let map = parse_map("#######\n#aa...#\n#a....#\n#..@..#\n#######");
+        assert_eq!(stuck_crates(&map), vec![(1,1)]);
+        let map = parse_map("#######\n#.a...#\n#aa...#\n#..@..#\n#######");
+        assert_eq!(stuck_crates(&map), vec![]);
+    }
+}
@@ -1,11 +1,15 @@
-#![allow(clippy::comparison_chain)]
+#[path = "common/paths.rs"]
+mod paths;
 
 use std::fs;
 use std::env;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::collections::hash_map::Entry;
+use paths::*;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -14,40 +18,103 @@ fn main() {
     let contents = fs::read_to_string(filename)
         .expect("Should have been able to read the file");
 
+    // optional: forward cost, turn cost and U-turn cost
+    let cost_args: Vec<usize> = args[2..].iter()
+        .take_while(|a| !a.starts_with("--"))
+        .map(|a| a.parse().unwrap())
+        .collect();
+    let mut costs = Costs::default();
+    if let Some(&forward) = cost_args.first() {
+        costs.forward = forward;
+    }
+    if let Some(&turn) = cost_args.get(1) {
+        costs.turn = turn;
+    }
+    if let Some(&u_turn) = cost_args.get(2) {
+        costs.u_turn = Some(u_turn);
+    }
+
     let laby = parse_map(&contents);
-    let best_infos = explore_base_paths(&laby);
+    let best_infos = explore_base_paths(&laby, &costs);
 
     let best_path = best_path_from_infos(&laby, &best_infos);
-    let cost = path_cost(&best_path);
+    let cost = path_cost(&laby, &costs, &best_path);
 
     let benches = explored_from_infos(&laby, &best_infos).len();
 
     println!("Result: {}", cost);
     println!("Result2: {}", benches);
-}
+    match count_best_paths_from_infos(&laby, &best_infos) {
+        Some(count) => println!("Best paths: {}", count),
+        None => println!("Best paths: too many to count"),
+    }
 
-const COST_FORWARD: usize = 1;
-const COST_TURN: usize = 1000;
+    if args.iter().any(|a| a == "--list") {
+        for path in all_best_paths_from_infos(&laby, &best_infos).unwrap_or_default() {
+            let repr: String = path.iter().map(|a| match a {
+                Action::Forward => 'F',
+                Action::TurnLeft => 'L',
+                Action::TurnRight => 'R',
+                Action::TurnAround => 'U',
+            }).collect();
+            println!("{}", repr);
+        }
+    }
+
+    if args.iter().any(|a| a == "--render") {
+        print!("{}", laby.repr_with_tiles(&explored_from_infos(&laby, &best_infos)));
+    }
+
+    if let Some(k) = option_value(&args, "--k") {
+        for (i, (cost, _)) in k_shortest_paths(&laby, &costs, k).iter().enumerate() {
+            println!("Path {}: {}", i+1, cost);
+        }
+    }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, std::hash::Hash)]
-enum Cell {
-    Empty,
-    Wall,
+    if let Some(delta) = option_value(&args, "--delta") {
+        let tiles = near_optimal_tiles(&laby, &costs, delta);
+        println!("Tiles within {} of the best: {}", delta, tiles.len());
+        if args.iter().any(|a| a == "--render") {
+            print!("{}", laby.repr_with_tiles(&tiles));
+        }
+    }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-struct Map {
-    cells: Vec<Vec<Cell>>,
-    start: (usize,usize),
-    end: (usize,usize)
+fn option_value(args: &[String], name: &str) -> Option<usize> {
+    args.iter()
+        .position(|a| a == name)
+        .map(|i| args[i+1].parse().unwrap())
 }
 
-impl Map {
-    fn get_size(&self) -> (usize,usize) {
-        (self.cells.len(), self.cells.first().unwrap().len())
+impl Default for Costs {
+    fn default() -> Self {
+        Costs{forward: 1, turn: 1000, u_turn: None}
     }
-    fn get_cell(&self, pos: (usize, usize)) -> Option<&Cell> {
-        self.cells.get(pos.0).and_then(|row| row.get(pos.1))
+}
+
+impl Map {
+    // the map as in the puzzle statement, with the given tiles drawn as `O`
+    fn repr_with_tiles(&self, tiles: &HashSet<(usize,usize)>) -> String {
+        let mut res: String = String::new();
+        for i in 0..self.cells.len() {
+            for j in 0..self.cells[i].len() {
+                if tiles.contains(&(i,j)) {
+                    res += "O";
+                } else if self.cells[i][j] == Cell::Wall {
+                    res += "#";
+                } else if (i,j) == self.start {
+                    res += "S";
+                } else if (i,j) == self.end {
+                    res += "E";
+                } else if self.weights[i][j] != 1 {
+                    res += &self.weights[i][j].to_string();
+                } else {
+                    res += ".";
+                }
+            }
+            res += "\n";
+        }
+        res
     }
 }
 
@@ -59,6 +126,9 @@ fn parse_map(s: &str) -> Map {
                 _ => Cell::Empty
             }).collect())
             .collect(),
+        weights: s.split_terminator('\n')
+            .map(|line| line.chars().map(|c| c.to_digit(10).unwrap_or(1) as usize).collect())
+            .collect(),
         start: s
             .split_terminator('\n')
             .enumerate()
@@ -84,260 +154,396 @@ fn parse_map(s: &str) -> Map {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, std::hash::Hash)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right
-}
+fn explored_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> HashSet<(usize,usize)> {
+    let mut back_explored: HashSet<((usize,usize),Direction)> = HashSet::new();
+    let mut wave : VecDeque<((usize,usize), Direction)> = VecDeque::new();
 
-impl Direction {
-    fn turn_left(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Up,
-            Direction::Up => Direction::Left,
-            Direction::Left => Direction::Down,
-            Direction::Down => Direction::Right,
+    let end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
+    let best_score = end_vec.iter().map(|k| infos.get(k).unwrap().distance).min().unwrap();
+
+    for posdir in infos.keys().filter(|e| e.0 == map.end) {
+        if infos.get(posdir).unwrap().distance == best_score {
+            wave.push_back(*posdir);
+            back_explored.insert(*posdir);
         }
     }
 
-    fn turn_right(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
-            Direction::Up => Direction::Right,
+    while !wave.is_empty() {
+        let explored = wave.pop_front().unwrap();
+        let ancestors = &infos.get(&explored).unwrap().parents;
+        for ancestor in ancestors {
+            if !back_explored.contains(ancestor) {
+                back_explored.insert(*ancestor);
+                wave.push_back(*ancestor);
+            }
         }
     }
-}
 
-fn get_moved_position(pos: (usize, usize), size: (usize, usize), dir: Direction) -> Option<(usize,usize)> {
-    match dir {
-        Direction::Down => {
-            if pos.0 +1 < size.0 {
-                Some((pos.0+1, pos.1))
-            } else {
-                None
-            }
-        },
-        Direction::Right => {
-            if pos.1 +1 < size.1 {
-                Some((pos.0, pos.1+1))
-            } else {
-                None
-            }
-        },
-        Direction::Left => {
-            if pos.1 > 0 {
-                Some((pos.0, pos.1-1))
-            } else {
-                None
-            }
-        },
-        Direction::Up => {
-            if pos.0 > 0 {
-                Some((pos.0-1, pos.1))
-            } else {
-                None
-            }
-        },
+    let mut res = HashSet::new();
+    for (pt,_) in back_explored {
+        res.insert(pt);
     }
+    res
 }
 
-#[derive(Clone, Copy)]
-enum Action {
-    TurnLeft,
-    TurnRight,
-    Forward,
+fn best_end_states(map: &Map, infos: &HashMap<State, ExploreInfo>) -> Vec<State> {
+    let best_score = infos.iter()
+        .filter(|(k,_)| k.0 == map.end)
+        .map(|(_,info)| info.distance)
+        .min();
+    infos.iter()
+        .filter(|(k,info)| k.0 == map.end && Some(info.distance) == best_score)
+        .map(|(k,_)| *k)
+        .collect()
 }
 
-struct ExploreInfo {
-    parents: Vec<((usize,usize),Direction)>,
-    parent_action: Vec<Action>,
-    distance: usize
+// parents a best path can come from: a path stops on the first end state it
+// reaches, so it never comes from another end state
+fn path_parents<'a>(map: &Map, infos: &'a HashMap<State, ExploreInfo>, state: State) -> impl Iterator<Item = (State, Action)> + 'a {
+    let end = map.end;
+    let info = infos.get(&state).unwrap();
+    info.parents.iter().copied()
+        .zip(info.parent_action.iter().copied())
+        .filter(move |(parent, _)| parent.0 != end)
 }
 
-fn insert_sorted_by<T,F>(vec: &mut VecDeque<T>, elem: T, mut sort_func: F)
-    where F: FnMut(&T)->usize
-{
-    let target_key = sort_func(&elem);
-    let search_res = vec.binary_search_by_key(&target_key, &mut sort_func);
-
-    if let Ok(i) = search_res {
-        vec.insert(i, elem);
-        //let distances: Vec<usize> = vec.iter().map(sort_func).collect();
-        //println!("After insert ok of {} at {}: {:?}", target_key, i, distances);
-    }
-    else if let Err(i) = search_res {
-        vec.insert(i, elem);
-        //let distances: Vec<usize> = vec.iter().map(sort_func).collect();
-        //println!("After insert err of {} at {}: {:?}", target_key, i, distances);
-    }
+// the parents graph with the states of a tile joined by free turns merged into
+// one group, so that paths only differing by the turns taken in place count once
+struct BestPathGroups {
+    origin: State,
+    // representative of the group of each state
+    group: HashMap<State, State>,
+    // free turns between states of a same group, both ways
+    turns: HashMap<State, Vec<(State, Action)>>,
+    // moves entering each group from another one: (parent, entered state, action)
+    entries: HashMap<State, Vec<(State, State, Action)>>,
+    // groups with the ones they are entered from first, None when free moves loop
+    order: Option<Vec<State>>,
 }
 
-fn explore_base_paths(map: &Map) -> HashMap<((usize,usize),Direction), ExploreInfo> {
-    let mut infos: HashMap<((usize,usize),Direction), ExploreInfo> = HashMap::new();
-    let mut to_explore: VecDeque<_> = VecDeque::from( [(map.start, Direction::Right)] );
-    infos.insert((map.start, Direction::Right), ExploreInfo{
-        parents: vec![],
-        parent_action: vec![],
-        distance:0
-    });
-
-    let mut prev_distance = 0;
-
-    while !to_explore.is_empty() {
-        let current = to_explore.pop_front().unwrap();
-        let mut needs_sort = false;
-        /*
-        if current.0 == map.end {
-            break;
+impl BestPathGroups {
+    fn new(map: &Map, infos: &HashMap<State, ExploreInfo>) -> BestPathGroups {
+        // a turn back undoes a free turn at the same cost
+        let mut turns: HashMap<State, Vec<(State, Action)>> = HashMap::new();
+        for (&state, info) in infos {
+            for (&parent, &action) in info.parents.iter().zip(&info.parent_action) {
+                if parent.0 == state.0 && infos.get(&parent).unwrap().distance == info.distance {
+                    let back = match action {
+                        Action::TurnLeft => Action::TurnRight,
+                        Action::TurnRight => Action::TurnLeft,
+                        other => other,
+                    };
+                    turns.entry(parent).or_default().push((state, action));
+                    turns.entry(state).or_default().push((parent, back));
+                }
+            }
         }
-        */
-        let current_distance = infos.get(&current).unwrap().distance;
-        //println!("Explore at distance {}", current_distance);
-        if prev_distance > current_distance {
-            println!("Explore at distance {} after {}", current_distance, prev_distance);
-            panic!();
-        }
-        prev_distance = current_distance;
-        // forward
-        let moved_forward = get_moved_position(current.0, map.get_size(), current.1);
-        if *map.get_cell(moved_forward.unwrap()).unwrap() == Cell::Empty {
-            let entry = infos.entry((moved_forward.unwrap(), current.1));
-            let new_info = ExploreInfo{
-                parents: vec![current],
-                parent_action: vec![Action::Forward],
-                distance: current_distance + COST_FORWARD,
-            };
-            match entry {
-                Entry::Vacant(v) => {
-                    v.insert(new_info);
-                    insert_sorted_by(&mut to_explore, (moved_forward.unwrap(), current.1), |e| infos.get(e).unwrap().distance);
-                },
-                Entry::Occupied(mut o) => {
-                    if o.get().distance > new_info.distance {
-                        o.insert(new_info);
-                        needs_sort = true;
-                    } else if o.get().distance == new_info.distance {
-                        o.get_mut().parents.push(current);
-                        o.get_mut().parent_action.push(Action::Forward);
+
+        let mut group: HashMap<State, State> = HashMap::new();
+        for &state in infos.keys() {
+            if group.contains_key(&state) {
+                continue;
+            }
+            group.insert(state, state);
+            let mut to_explore = vec![state];
+            while let Some(current) = to_explore.pop() {
+                for &(next, _) in turns.get(&current).into_iter().flatten() {
+                    if let Entry::Vacant(v) = group.entry(next) {
+                        v.insert(state);
+                        to_explore.push(next);
                     }
                 }
-            };
-        }
-        // left turn
-        {
-            let entry = infos.entry((current.0, current.1.turn_left()));
-            let new_info = ExploreInfo{
-                parents: vec![current],
-                parent_action: vec![Action::TurnLeft],
-                distance: current_distance + COST_TURN,
-            };
-            match entry {
-                Entry::Vacant(v) => {
-                    v.insert(new_info);
-                    insert_sorted_by(&mut to_explore, (current.0, current.1.turn_left()), |e| infos.get(e).unwrap().distance);
-                },
-                Entry::Occupied(mut o) => {
-                    if o.get().distance > new_info.distance {
-                        o.insert(new_info);
-                        needs_sort = true;
-                    } else if o.get().distance == new_info.distance {
-                        o.get_mut().parents.push(current);
-                        o.get_mut().parent_action.push(Action::TurnLeft);
-                    }
+            }
+        }
+
+        let mut entries: HashMap<State, Vec<(State, State, Action)>> = HashMap::new();
+        for &state in infos.keys() {
+            for (parent, action) in path_parents(map, infos, state) {
+                if group[&parent] != group[&state] {
+                    entries.entry(group[&state]).or_default().push((parent, state, action));
                 }
-            };
-        }
-        // right turn
-        {
-            let entry = infos.entry((current.0, current.1.turn_right()));
-            let new_info = ExploreInfo{
-                parents: vec![current],
-                parent_action: vec![Action::TurnRight],
-                distance: current_distance + COST_TURN,
-            };
-            match entry {
-                Entry::Vacant(v) => {
-                    v.insert(new_info);
-                    insert_sorted_by(&mut to_explore, (current.0, current.1.turn_right()), |e| infos.get(e).unwrap().distance);
-                },
-                Entry::Occupied(mut o) => {
-                    if o.get().distance > new_info.distance {
-                        o.insert(new_info);
-                        needs_sort = true;
-                    } else if o.get().distance == new_info.distance {
-                        o.get_mut().parents.push(current);
-                        o.get_mut().parent_action.push(Action::TurnRight);
-                    }
+            }
+        }
+
+        // only free moves forward can make the groups loop, sort them topologically
+        let mut children: HashMap<State, Vec<State>> = HashMap::new();
+        let mut missing: HashMap<State, usize> = group.values().map(|&g| (g, 0)).collect();
+        for (&g, moves) in &entries {
+            for (parent, _, _) in moves {
+                children.entry(group[parent]).or_default().push(g);
+                *missing.get_mut(&g).unwrap() += 1;
+            }
+        }
+        let mut ready: Vec<State> = missing.iter().filter(|(_, &n)| n == 0).map(|(&g, _)| g).collect();
+        let mut order = vec![];
+        while let Some(g) = ready.pop() {
+            order.push(g);
+            for child in children.get(&g).into_iter().flatten() {
+                let n = missing.get_mut(child).unwrap();
+                *n -= 1;
+                if *n == 0 {
+                    ready.push(*child);
                 }
-            };
+            }
         }
-        if needs_sort {
-            to_explore.make_contiguous().sort_by_key(|e| infos.get(e).unwrap().distance);
+        let order = (order.len() == missing.len()).then_some(order);
+
+        BestPathGroups{origin: (map.start, Direction::Right), group, turns, entries, order}
+    }
+
+    // fewest free turns from one state of a group to another
+    fn turns_between(&self, from: State, to: State) -> Vec<Action> {
+        let mut previous: HashMap<State, (State, Action)> = HashMap::new();
+        let mut wave = VecDeque::from([from]);
+        while let Some(current) = wave.pop_front() {
+            if current == to {
+                break;
+            }
+            for &(next, action) in self.turns.get(&current).into_iter().flatten() {
+                if next != from && !previous.contains_key(&next) {
+                    previous.insert(next, (current, action));
+                    wave.push_back(next);
+                }
+            }
+        }
+        let mut res = vec![];
+        let mut current = to;
+        while current != from {
+            let (prev, action) = previous[&current];
+            res.push(action);
+            current = prev;
         }
+        res.reverse();
+        res
     }
+}
 
-    infos
+// groups holding a best end state, each once
+fn best_end_groups(map: &Map, infos: &HashMap<State, ExploreInfo>, groups: &BestPathGroups) -> Vec<State> {
+    best_end_states(map, infos).into_iter()
+        .map(|state| groups.group[&state])
+        .collect::<HashSet<State>>()
+        .into_iter()
+        .collect()
 }
 
-fn best_path_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> Vec<Action> {
-    // build backwards
-    let mut end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
-    end_vec.sort_by_key(|item| infos.get(item).unwrap().distance);
-    let mut reached = end_vec.first().cloned();
-    let mut res: Vec<Action> = vec![];
-    while reached.is_some() {
-        let pos = reached.unwrap();
-        let info = infos.get(&pos).unwrap();
-        reached = info.parents.first().cloned();
-        if let Some(act) = info.parent_action.first() {
-            res.push(*act);
-        }
-    }
-    res.reverse();
-    res
+// number of distinct optimal paths, the free turns taken in place aside; None
+// when it does not fit in a usize or when free moves can loop forever
+fn count_best_paths_from_infos(map: &Map, infos: &HashMap<State, ExploreInfo>) -> Option<usize> {
+    let groups = BestPathGroups::new(map, infos);
+    let mut counts: HashMap<State, usize> = HashMap::new();
+    for &g in groups.order.as_ref()? {
+        let count = if g == groups.group[&groups.origin] {
+            1
+        } else {
+            groups.entries.get(&g).into_iter().flatten()
+                .try_fold(0usize, |acc, (parent, _, _)| acc.checked_add(counts[&groups.group[parent]]))?
+        };
+        counts.insert(g, count);
+    }
+    best_end_groups(map, infos, &groups).into_iter()
+        .try_fold(0usize, |acc, g| acc.checked_add(counts[&g]))
 }
 
-fn explored_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> HashSet<(usize,usize)> {
-    let mut back_explored: HashSet<((usize,usize),Direction)> = HashSet::new();
-    let mut wave : VecDeque<((usize,usize), Direction)> = VecDeque::new();
+// one action sequence for each path counted by count_best_paths_from_infos, with
+// the fewest turns in place; None when free moves can loop forever
+fn all_best_paths_from_infos(map: &Map, infos: &HashMap<State, ExploreInfo>) -> Option<Vec<Vec<Action>>> {
+    // the actions after `exit` are in `suffix`, reversed
+    fn paths_to(groups: &BestPathGroups, exit: State, suffix: &mut Vec<Action>, res: &mut Vec<Vec<Action>>) {
+        let g = groups.group[&exit];
+        if g == groups.group[&groups.origin] {
+            let mut path = groups.turns_between(groups.origin, exit);
+            path.extend(suffix.iter().rev());
+            res.push(path);
+            return;
+        }
+        for &(parent, entered, action) in groups.entries.get(&g).into_iter().flatten() {
+            let turns = groups.turns_between(entered, exit);
+            let len = suffix.len();
+            suffix.extend(turns.iter().rev());
+            suffix.push(action);
+            paths_to(groups, parent, suffix, res);
+            suffix.truncate(len);
+        }
+    }
 
-    let end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
-    let best_score = end_vec.iter().map(|k| infos.get(k).unwrap().distance).min().unwrap();
+    let groups = BestPathGroups::new(map, infos);
+    groups.order.as_ref()?;
+    let mut res = vec![];
+    // a path stops on the end state it enters the group with
+    for g in best_end_groups(map, infos, &groups) {
+        if g == groups.group[&groups.origin] {
+            res.push(vec![]);
+            continue;
+        }
+        for &(parent, _, action) in groups.entries.get(&g).into_iter().flatten() {
+            paths_to(&groups, parent, &mut vec![action], &mut res);
+        }
+    }
+    Some(res)
+}
 
-    for posdir in infos.keys().filter(|e| e.0 == map.end) {
-        if infos.get(posdir).unwrap().distance == best_score {
-            wave.push_back(*posdir);
-            back_explored.insert(*posdir);
+// states from which the given one is reached in one action, with the action cost
+fn previous_states(map: &Map, costs: &Costs, current: State) -> Vec<(State, usize)> {
+    let (pos, dir) = current;
+    let mut res = vec![];
+    if let Some(pos_back) = get_moved_position(pos, map.get_size(), dir.turn_around()) {
+        if *map.get_cell(pos_back).unwrap() == Cell::Empty {
+            res.push(((pos_back, dir), costs.forward * map.get_weight(pos)));
+        }
+    }
+    res.push(((pos, dir.turn_right()), costs.turn));
+    res.push(((pos, dir.turn_left()), costs.turn));
+    if let Some(u_turn) = costs.u_turn {
+        res.push(((pos, dir.turn_around()), u_turn));
+    }
+    res
+}
+
+// cheapest cost from every state to the end, whatever the final direction
+fn distances_to_end(map: &Map, costs: &Costs) -> HashMap<State,usize> {
+    let mut res: HashMap<State,usize> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for dir in [Direction::Up, Direction::Down, Direction::Left, Direction::Right] {
+        res.insert((map.end, dir), 0);
+        queue.push(Reverse((0, (map.end, dir))));
+    }
+    while let Some(Reverse((dist, state))) = queue.pop() {
+        if res[&state] < dist {
+            continue;
+        }
+        for (prev, cost) in previous_states(map, costs, state) {
+            if res.get(&prev).is_none_or(|&d| dist + cost < d) {
+                res.insert(prev, dist + cost);
+                queue.push(Reverse((dist + cost, prev)));
+            }
         }
     }
+    res
+}
 
-    while !wave.is_empty() {
-        let explored = wave.pop_front().unwrap();
-        let ancestors = &infos.get(&explored).unwrap().parents;
-        for ancestor in ancestors {
-            if !back_explored.contains(ancestor) {
-                back_explored.insert(*ancestor);
-                wave.push_back(*ancestor);
+// tiles on some route from start to end costing at most the best one plus delta,
+// which is explored_from_infos for a delta of 0
+fn near_optimal_tiles(map: &Map, costs: &Costs, delta: usize) -> HashSet<(usize,usize)> {
+    let from_start = explore_base_paths(map, costs);
+    let to_end = distances_to_end(map, costs);
+    let Some(best) = to_end.get(&(map.start, Direction::Right)) else {
+        return HashSet::new();
+    };
+    from_start.iter()
+        .filter(|(state, info)| to_end.get(state).is_some_and(|d| info.distance + d <= best + delta))
+        .map(|(state, _)| state.0)
+        .collect()
+}
+
+// cheapest path from `origin` to the end over the state graph, not going
+// through the removed states or along the removed transitions
+fn shortest_state_path(map: &Map, costs: &Costs, origin: State, removed_states: &HashSet<State>, removed_moves: &HashSet<(State,State)>) -> Option<(usize, Vec<State>)> {
+    let mut best: HashMap<State,(usize,Option<State>)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    best.insert(origin, (0, None));
+    queue.push(Reverse((0, origin)));
+    while let Some(Reverse((dist, state))) = queue.pop() {
+        if best[&state].0 < dist {
+            continue;
+        }
+        if state.0 == map.end {
+            let mut res = vec![state];
+            while let Some(parent) = best[res.last().unwrap()].1 {
+                res.push(parent);
+            }
+            res.reverse();
+            return Some((dist, res));
+        }
+        for (next, _, cost) in next_states(map, costs, state) {
+            if removed_states.contains(&next) || removed_moves.contains(&(state, next)) {
+                continue;
+            }
+            if best.get(&next).is_none_or(|&(d,_)| dist + cost < d) {
+                best.insert(next, (dist + cost, Some(state)));
+                queue.push(Reverse((dist + cost, next)));
             }
         }
     }
+    None
+}
 
-    let mut res = HashSet::new();
-    for (pt,_) in back_explored {
-        res.insert(pt);
+fn state_path_actions(map: &Map, costs: &Costs, path: &[State]) -> Vec<Action> {
+    path.windows(2)
+        .map(|w| next_states(map, costs, w[0]).into_iter().find(|(next,_,_)| *next == w[1]).unwrap().1)
+        .collect()
+}
+
+// the k cheapest loopless paths over the (position, direction) graph, by Yen's algorithm,
+// a path stops as soon as it reaches the end tile
+fn k_shortest_paths(map: &Map, costs: &Costs, k: usize) -> Vec<(usize, Vec<Action>)> {
+    let step_cost = |a: State, b: State| next_states(map, costs, a).into_iter().find(|(next,_,_)| *next == b).unwrap().2;
+    let origin = (map.start, Direction::Right);
+
+    let mut found: Vec<(usize, Vec<State>)> = vec![];
+    let mut candidates: BinaryHeap<Reverse<(usize, Vec<State>)>> = BinaryHeap::new();
+    let mut seen: HashSet<Vec<State>> = HashSet::new();
+    if let Some(first) = shortest_state_path(map, costs, origin, &HashSet::new(), &HashSet::new()) {
+        seen.insert(first.1.clone());
+        candidates.push(Reverse(first));
     }
-    res
+
+    while found.len() < k {
+        let Some(Reverse(path)) = candidates.pop() else {
+            break;
+        };
+        let states = &path.1;
+        let mut root_cost = 0;
+        for i in 0..states.len()-1 {
+            let root = &states[..=i];
+            let removed_moves: HashSet<(State,State)> = found.iter()
+                .chain(std::iter::once(&path))
+                .filter(|(_, other)| other.len() > i+1 && other[..=i] == *root)
+                .map(|(_, other)| (other[i], other[i+1]))
+                .collect();
+            let removed_states: HashSet<State> = root[..i].iter().copied().collect();
+            if let Some((spur_cost, spur)) = shortest_state_path(map, costs, states[i], &removed_states, &removed_moves) {
+                let mut candidate = root[..i].to_vec();
+                candidate.extend(spur);
+                if seen.insert(candidate.clone()) {
+                    candidates.push(Reverse((root_cost + spur_cost, candidate)));
+                }
+            }
+            root_cost += step_cost(states[i], states[i+1]);
+        }
+        found.push(path);
+    }
+
+    found.into_iter()
+        .map(|(cost, states)| (cost, state_path_actions(map, costs, &states)))
+        .collect()
 }
 
-fn path_cost(actions: &[Action]) -> usize {
-    actions.iter().map(|act| match act {
-        Action::Forward => COST_FORWARD,
-        _ => COST_TURN
-    }).sum()
+fn path_cost(map: &Map, costs: &Costs, actions: &[Action]) -> usize {
+    let mut pos = map.start;
+    let mut dir = Direction::Right;
+    let mut res = 0;
+    for act in actions {
+        match act {
+            Action::Forward => {
+                pos = get_moved_position(pos, map.get_size(), dir).unwrap();
+                res += costs.forward * map.get_weight(pos);
+            },
+            Action::TurnLeft => {
+                dir = dir.turn_left();
+                res += costs.turn;
+            },
+            Action::TurnRight => {
+                dir = dir.turn_right();
+                res += costs.turn;
+            },
+            Action::TurnAround => {
+                dir = dir.turn_around();
+                res += costs.u_turn.unwrap();
+            },
+        }
+    }
+    res
 }
 
 #[cfg(test)]
@@ -347,13 +553,243 @@ mod tests {
     #[test]
     fn example() {
         let laby = parse_map("###############\n#.......#....E#\n#.#.###.#.###.#\n#.....#.#...#.#\n#.###.#####.#.#\n#.#.#.......#.#\n#.#.#####.###.#\n#...........#.#\n###.#.#####.#.#\n#...#.....#.#.#\n#.#.#.###.#.#.#\n#.....#...#.#.#\n#.###.#.#.#.#.#\n#S..#.....#...#\n###############\n");
-        let best_infos = explore_base_paths(&laby);
+        let best_infos = explore_base_paths(&laby, &Costs::default());
 
         let best_path = best_path_from_infos(&laby, &best_infos);
-        let cost = path_cost(&best_path);
+        let cost = path_cost(&laby, &Costs::default(), &best_path);
         assert_eq!(cost, 7036);
 
         let bench = explored_from_infos(&laby, &best_infos);
         assert_eq!(bench.len(), 45);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn example_all_paths() {
+        let laby = parse_map("###############\n#.......#....E#\n#.#.###.#.###.#\n#.....#.#...#.#\n#.###.#####.#.#\n#.#.#.......#.#\n#.#.#####.###.#\n#...........#.#\n###.#.#####.#.#\n#...#.....#.#.#\n#.#.#.###.#.#.#\n#.....#...#.#.#\n#.###.#.#.#.#.#\n#S..#.....#...#\n###############\n");
+        let best_infos = explore_base_paths(&laby, &Costs::default());
+
+        let paths = all_best_paths_from_infos(&laby, &best_infos).unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(count_best_paths_from_infos(&laby, &best_infos), Some(3));
+        for path in &paths {
+            assert_eq!(path_cost(&laby, &Costs::default(), path), 7036);
+        }
+        assert!(paths.contains(&best_path_from_infos(&laby, &best_infos)));
+
+        assert_eq!(laby.repr_with_tiles(&explored_from_infos(&laby, &best_infos)), "\
+###############
+#.......#....O#
+#.#.###.#.###O#
+#.....#.#...#O#
+#.###.#####.#O#
+#.#.#.......#O#
+#.#.#####.###O#
+#..OOOOOOOOO#O#
+###O#O#####O#O#
+#OOO#O....#O#O#
+#O#O#O###.#O#O#
+#OOOOO#...#O#O#
+#O###.#.#.#O#O#
+#O..#.....#OOO#
+###############
+");
+    }
+
+    #[test]
+    fn example_k_shortest() {
+        let laby = parse_map("###############\n#.......#....E#\n#.#.###.#.###.#\n#.....#.#...#.#\n#.###.#####.#.#\n#.#.#.......#.#\n#.#.#####.###.#\n#...........#.#\n###.#.#####.#.#\n#...#.....#.#.#\n#.#.#.###.#.#.#\n#.....#...#.#.#\n#.###.#.#.#.#.#\n#S..#.....#...#\n###############\n");
+        let costs = Costs::default();
+
+        let paths = k_shortest_paths(&laby, &costs, 5);
+        let path_costs: Vec<usize> = paths.iter().map(|(cost,_)| *cost).collect();
+        assert_eq!(path_costs[..3], [7036, 7036, 7036]);
+        assert!(path_costs[3] > 7036);
+        assert!(path_costs.windows(2).all(|w| w[0] <= w[1]));
+        for (cost, actions) in &paths {
+            assert_eq!(path_cost(&laby, &costs, actions), *cost);
+        }
+
+        let best_infos = explore_base_paths(&laby, &costs);
+        assert_eq!(near_optimal_tiles(&laby, &costs, 0), explored_from_infos(&laby, &best_infos));
+        // the runner-up takes the best route with three left turns for a right one
+        assert_eq!(path_costs[3], 9036);
+        assert_eq!(near_optimal_tiles(&laby, &costs, 2000).len(), 45);
+        let near = near_optimal_tiles(&laby, &costs, 4000);
+        assert!(near.len() > 45);
+        assert!(near.is_superset(&explored_from_infos(&laby, &best_infos)));
+        let open_tiles = laby.cells.iter().flatten().filter(|c| **c == Cell::Empty).count();
+        assert_eq!(near_optimal_tiles(&laby, &costs, 1_000_000).len(), open_tiles);
+    }
+
+    #[test]
+    fn k_shortest_brute_force() {
+        // every loopless state path, stopping on the end tile
+        fn all_paths(map: &Map, costs: &Costs, path: &mut Vec<State>, cost: usize, res: &mut Vec<usize>) {
+            let current = *path.last().unwrap();
+            if current.0 == map.end {
+                res.push(cost);
+                return;
+            }
+            for (next, _, step) in next_states(map, costs, current) {
+                if !path.contains(&next) {
+                    path.push(next);
+                    all_paths(map, costs, path, cost + step, res);
+                    path.pop();
+                }
+            }
+        }
+
+        let laby = parse_map("#####\n#..E#\n#S.2#\n#####\n");
+        for costs in [Costs::default(), Costs{forward: 3, turn: 2, u_turn: Some(1)}] {
+            let mut expected = vec![];
+            all_paths(&laby, &costs, &mut vec![(laby.start, Direction::Right)], 0, &mut expected);
+            expected.sort();
+
+            let paths = k_shortest_paths(&laby, &costs, 30);
+            let path_costs: Vec<usize> = paths.iter().map(|(cost,_)| *cost).collect();
+            assert_eq!(path_costs, expected[..30.min(expected.len())]);
+        }
+    }
+
+    #[test]
+    fn symmetric_near_optimal() {
+        let laby = parse_map("#######\n#.....#\n#S.#.E#\n#.....#\n#######\n");
+        let costs = Costs::default();
+        assert_eq!(near_optimal_tiles(&laby, &costs, 0).len(), 13);
+        assert_eq!(near_optimal_tiles(&laby, &costs, 999).len(), 13);
+        assert_eq!(near_optimal_tiles(&laby, &costs, 1000).len(), 14);
+        assert_eq!(k_shortest_paths(&laby, &costs, 5).iter().map(|(c,_)| *c).collect::<Vec<_>>(), vec![3006, 3006, 3006, 3006, 4006]);
+    }
+
+    #[test]
+    fn symmetric_paths() {
+        // around the middle wall by the top or the bottom, turning off the start row early or late
+        let laby = parse_map("#######\n#.....#\n#S.#.E#\n#.....#\n#######\n");
+        let best_infos = explore_base_paths(&laby, &Costs::default());
+        let paths = all_best_paths_from_infos(&laby, &best_infos).unwrap();
+        assert_eq!(paths.len(), 4);
+        assert_eq!(count_best_paths_from_infos(&laby, &best_infos), Some(4));
+        for path in &paths {
+            assert_eq!(path_cost(&laby, &Costs::default(), path), 3006);
+        }
+        assert_eq!(laby.repr_with_tiles(&explored_from_infos(&laby, &best_infos)), "#######\n#OOOOO#\n#OO#.O#\n#OOOOO#\n#######\n");
+        assert_eq!(laby.repr_with_tiles(&HashSet::new()), "#######\n#.....#\n#S.#.E#\n#.....#\n#######\n");
+    }
+
+    #[test]
+    fn custom_costs() {
+        let laby = parse_map("#######\n#....E#\n#.###.#\n#S....#\n#######\n");
+
+        // right 4, turn, up 2 is one turn less than up 2, turn, right 4
+        let costs = Costs{forward: 3, turn: 10, u_turn: None};
+        let best_infos = explore_base_paths(&laby, &costs);
+        let best_path = best_path_from_infos(&laby, &best_infos);
+        assert_eq!(path_cost(&laby, &costs, &best_path), 6*3 + 10);
+        assert_eq!(explored_from_infos(&laby, &best_infos).len(), 7);
+
+        // free turns make both ways equally good
+        let costs = Costs{forward: 1, turn: 0, u_turn: None};
+        let best_infos = explore_base_paths(&laby, &costs);
+        assert_eq!(path_cost(&laby, &costs, &best_path_from_infos(&laby, &best_infos)), 6);
+        assert_eq!(explored_from_infos(&laby, &best_infos).len(), 12);
+    }
+
+    #[test]
+    fn u_turn() {
+        let laby = parse_map("#####\n#E.S#\n#####\n");
+
+        // two quarter turns unless a U-turn is cheaper
+        let costs = Costs::default();
+        let best_infos = explore_base_paths(&laby, &costs);
+        let best_path = best_path_from_infos(&laby, &best_infos);
+        assert_eq!(path_cost(&laby, &costs, &best_path), 2002);
+
+        let costs = Costs{u_turn: Some(500), ..Costs::default()};
+        let best_infos = explore_base_paths(&laby, &costs);
+        let best_path = best_path_from_infos(&laby, &best_infos);
+        assert_eq!(best_path, vec![Action::TurnAround, Action::Forward, Action::Forward]);
+        assert_eq!(path_cost(&laby, &costs, &best_path), 502);
+    }
+
+    #[test]
+    fn weighted_cells() {
+        // the short way crosses expensive cells
+        let laby = parse_map("#######\n#....E#\n#.###9#\n#S...9#\n#######\n");
+        let costs = Costs{forward: 1, turn: 0, u_turn: None};
+        let best_infos = explore_base_paths(&laby, &costs);
+        let best_path = best_path_from_infos(&laby, &best_infos);
+        assert_eq!(path_cost(&laby, &costs, &best_path), 6);
+        assert_eq!(best_path.iter().filter(|a| **a == Action::Forward).count(), 6);
+
+        let laby = parse_map("#######\n#2222E#\n#.###9#\n#S...9#\n#######\n");
+        let best_infos = explore_base_paths(&laby, &costs);
+        let best_path = best_path_from_infos(&laby, &best_infos);
+        assert_eq!(path_cost(&laby, &costs, &best_path), 1+8+1);
+        assert_eq!(path_cost(&laby, &costs, &best_path), best_infos.iter()
+            .filter(|(k,_)| k.0 == laby.end)
+            .map(|(_,info)| info.distance)
+            .min()
+            .unwrap());
+    }
+
+    #[test]
+    fn free_turns() {
+        // both ways around the pillar cost 6 once turning is free
+        let laby = parse_map("#######\n#S..###\n#.#.###\n#...###\n###.###\n###E###\n#######\n");
+        let costs = Costs{forward: 1, turn: 0, u_turn: None};
+        let best_infos = explore_base_paths(&laby, &costs);
+        assert_eq!(path_cost(&laby, &costs, &best_path_from_infos(&laby, &best_infos)), 6);
+        let tiles = explored_from_infos(&laby, &best_infos);
+        assert_eq!(tiles.len(), 10);
+        assert_eq!(near_optimal_tiles(&laby, &costs, 0), tiles);
+
+        // turning right or three times left at a corner is the same path,
+        // which leaves the ways along the top and around the bottom
+        assert_eq!(count_best_paths_from_infos(&laby, &best_infos), Some(2));
+        let paths = all_best_paths_from_infos(&laby, &best_infos).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths.iter().collect::<HashSet<_>>().len(), 2);
+        for path in &paths {
+            assert_eq!(path_cost(&laby, &costs, path), 6);
+        }
+    }
+
+    #[test]
+    fn open_room_free_turns() {
+        // every best path is a staircase of n moves up and n moves right
+        fn room(n: usize) -> Map {
+            let mut rows = vec!["#".repeat(n+3)];
+            rows.extend((0..=n).map(|i| match i {
+                0 => format!("#{}E#", ".".repeat(n)),
+                i if i == n => format!("#S{}#", ".".repeat(n)),
+                _ => format!("#{}#", ".".repeat(n+1)),
+            }));
+            rows.push("#".repeat(n+3));
+            parse_map(&(rows.join("\n") + "\n"))
+        }
+        fn binomial(n: u128, k: u128) -> u128 {
+            (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+        }
+
+        let costs = Costs{forward: 1, turn: 0, u_turn: Some(0)};
+        let laby = room(3);
+        let best_infos = explore_base_paths(&laby, &costs);
+        let paths = all_best_paths_from_infos(&laby, &best_infos).unwrap();
+        assert_eq!(paths.len(), 20);
+        assert_eq!(paths.iter().collect::<HashSet<_>>().len(), 20);
+        for path in &paths {
+            assert_eq!(path_cost(&laby, &costs, path), 6);
+        }
+        assert_eq!(count_best_paths_from_infos(&laby, &best_infos), Some(20));
+
+        for n in [8, 30] {
+            let laby = room(n);
+            let best_infos = explore_base_paths(&laby, &costs);
+            assert_eq!(count_best_paths_from_infos(&laby, &best_infos).map(|c| c as u128), Some(binomial(2 * n as u128, n as u128)));
+        }
+        // C(80, 40) does not fit in 64 bits
+        let laby = room(40);
+        let best_infos = explore_base_paths(&laby, &costs);
+        assert_eq!(count_best_paths_from_infos(&laby, &best_infos), None);
+    }
+}
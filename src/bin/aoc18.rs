@@ -1,10 +1,13 @@
-#![allow(clippy::comparison_chain)]
+#[path = "common/paths.rs"]
+mod paths;
+#[cfg(test)]
+#[path = "common/lcg.rs"]
+mod lcg;
 
 use std::fs;
 use std::env;
-use std::collections::{VecDeque,HashMap};
-use std::collections::hash_map::Entry;
-
+use std::collections::HashMap;
+use paths::*;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -15,66 +18,122 @@ fn main() {
 
     let laby = Map::empty(71, 71);
     let mut fw_map = laby.clone();
-    let all_blocks = parse_pos(&contents);
-    for (i,j) in all_blocks[..1024].iter() {
-        *fw_map.get_cell_mut((*j,*i)).unwrap() = Cell::Wall;
-    }
+    let all_blocks = or_exit(parse_pos(&contents));
+    or_exit(fw_map.drop_bytes(&all_blocks[..all_blocks.len().min(1024)]));
 
-    let shortest_info = explore_base_paths(&fw_map);
+    let shortest_info = explore_base_paths(&fw_map, &WALK_COSTS);
     let path = best_path_from_infos(&fw_map, &shortest_info);
 
     let shortest_path_len = path.iter().filter(|a| **a == Action::Forward).count();
 
-    let first_blocker = first_blocker(laby, &all_blocks);
-
     println!("Result: {}", shortest_path_len);
-    println!("Result2: {},{}", first_blocker.0, first_blocker.1);
+    match or_exit(first_blocker(&laby, &all_blocks)) {
+        Some((k, blocker)) => {
+            println!("Result2: {},{}", blocker.x, blocker.y);
+            println!("Blocking byte index: {}", k);
+        },
+        None => println!("Result2: no byte blocks the exit"),
+    }
+    match or_exit(escape_while_falling(&laby, &all_blocks)) {
+        Some(time) => println!("Escape while bytes fall: {}", time),
+        None => println!("Escape while bytes fall: impossible"),
+    }
 }
 
-fn parse_pos(s: &str) -> Vec<(usize,usize)> {
-    s.split_terminator("\n")
-        .map(|line| {
-            let mut it = line.split(",");
-            (it.next().unwrap().parse().unwrap(), it.next().unwrap().parse().unwrap())
-        })
-        .collect()
+// invalid input is reported without a backtrace
+fn or_exit<T>(res: Result<T, ByteError>) -> T {
+    res.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
 }
 
-const COST_FORWARD: usize = 1;
-const COST_TURN: usize = 0;
+// position of a byte as given in the input: distance from the left edge, then from the top
+#[derive(Clone, Copy, PartialEq, Eq, Debug, std::hash::Hash)]
+struct Point {
+    x: usize,
+    y: usize,
+}
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, std::hash::Hash)]
-enum Cell {
-    Empty,
-    Wall,
+#[derive(PartialEq, Eq, Debug)]
+enum ByteError {
+    Parse{line: usize},
+    OutOfBounds{index: usize, pos: Point},
+    Duplicate{index: usize, first: usize, pos: Point},
 }
 
-#[derive(PartialEq, Eq, Debug, Clone)]
-struct Map {
-    cells: Vec<Vec<Cell>>,
-    start: (usize,usize),
-    end: (usize,usize)
+impl std::fmt::Display for ByteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ByteError::Parse{line} => write!(f, "line {} is not a x,y position", line+1),
+            ByteError::OutOfBounds{index, pos} => write!(f, "byte {} at {},{} is outside the memory space", index, pos.x, pos.y),
+            ByteError::Duplicate{index, first, pos} => write!(f, "byte {} at {},{} falls where byte {} already fell", index, pos.x, pos.y, first),
+        }
+    }
 }
 
+impl std::error::Error for ByteError {}
+
+fn parse_pos(s: &str) -> Result<Vec<Point>, ByteError> {
+    s.split_terminator("\n")
+        .enumerate()
+        .map(|(line, text)| {
+            let (x, y) = text.split_once(",").ok_or(ByteError::Parse{line})?;
+            Ok(Point{
+                x: x.parse().map_err(|_| ByteError::Parse{line})?,
+                y: y.parse().map_err(|_| ByteError::Parse{line})?,
+            })
+        })
+        .collect()
+}
+
+// free turns make the reindeer engine a plain grid walk
+const WALK_COSTS: Costs = Costs{forward: 1, turn: 0, u_turn: None};
+
 impl Map {
     fn empty(height: usize, width: usize) -> Map {
         Map{
             cells: (0..height)
                 .map(|_| (0..width).map(|_| Cell::Empty).collect())
                 .collect(),
+            weights: vec![vec![1; width]; height],
             start: (0,0),
             end: (height-1,width-1)
         }
     }
-    fn get_size(&self) -> (usize,usize) {
-        (self.cells.len(), self.cells.first().unwrap().len())
-    }
-    fn get_cell(&self, pos: (usize, usize)) -> Option<&Cell> {
-        self.cells.get(pos.0).and_then(|row| row.get(pos.1))
-    }
     fn get_cell_mut(&mut self, pos: (usize, usize)) -> Option<&mut Cell> {
         self.cells.get_mut(pos.0).and_then(|row| row.get_mut(pos.1))
     }
+    // the only place where byte positions become (row, column) grid positions
+    fn grid_pos(&self, point: Point) -> Option<(usize,usize)> {
+        let size = self.get_size();
+        if point.y < size.0 && point.x < size.1 {
+            Some((point.y, point.x))
+        } else {
+            None
+        }
+    }
+    // grid positions of the bytes, checking they are in the map and fall on distinct cells
+    fn byte_cells(&self, bytes: &[Point]) -> Result<Vec<(usize,usize)>, ByteError> {
+        let mut first_fallen: HashMap<(usize,usize),usize> = HashMap::new();
+        bytes.iter()
+            .enumerate()
+            .map(|(index, &pos)| {
+                let cell = self.grid_pos(pos).ok_or(ByteError::OutOfBounds{index, pos})?;
+                if let Some(&first) = first_fallen.get(&cell) {
+                    return Err(ByteError::Duplicate{index, first, pos});
+                }
+                first_fallen.insert(cell, index);
+                Ok(cell)
+            })
+            .collect()
+    }
+    fn drop_bytes(&mut self, bytes: &[Point]) -> Result<(), ByteError> {
+        for cell in self.byte_cells(bytes)? {
+            *self.get_cell_mut(cell).unwrap() = Cell::Wall;
+        }
+        Ok(())
+    }
     fn _repr(&self) -> String {
         let mut res: String = String::new();
         for i in 0..self.cells.len() {
@@ -91,285 +150,147 @@ impl Map {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, std::hash::Hash)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right
+// disjoint sets over cell indices, with path halving and union by size
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
 }
 
-impl Direction {
-    fn turn_left(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Up,
-            Direction::Up => Direction::Left,
-            Direction::Left => Direction::Down,
-            Direction::Down => Direction::Right,
-        }
+impl DisjointSet {
+    fn new(count: usize) -> DisjointSet {
+        DisjointSet{parent: (0..count).collect(), size: vec![1; count]}
     }
 
-    fn turn_right(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
-            Direction::Up => Direction::Right,
+    fn find(&mut self, mut item: usize) -> usize {
+        while self.parent[item] != item {
+            self.parent[item] = self.parent[self.parent[item]];
+            item = self.parent[item];
         }
+        item
     }
-}
 
-fn get_moved_position(pos: (usize, usize), size: (usize, usize), dir: Direction) -> Option<(usize,usize)> {
-    match dir {
-        Direction::Down => {
-            if pos.0 +1 < size.0 {
-                Some((pos.0+1, pos.1))
-            } else {
-                None
-            }
-        },
-        Direction::Right => {
-            if pos.1 +1 < size.1 {
-                Some((pos.0, pos.1+1))
-            } else {
-                None
-            }
-        },
-        Direction::Left => {
-            if pos.1 > 0 {
-                Some((pos.0, pos.1-1))
-            } else {
-                None
-            }
-        },
-        Direction::Up => {
-            if pos.0 > 0 {
-                Some((pos.0-1, pos.1))
-            } else {
-                None
-            }
-        },
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum Action {
-    TurnLeft,
-    TurnRight,
-    Forward,
-}
-
-struct ExploreInfo {
-    parents: Vec<((usize,usize),Direction)>,
-    parent_action: Vec<Action>,
-    distance: usize
-}
-
-fn insert_sorted_by<T,F>(vec: &mut VecDeque<T>, elem: T, mut sort_func: F)
-    where F: FnMut(&T)->usize
-{
-    let target_key = sort_func(&elem);
-    let search_res = vec.binary_search_by_key(&target_key, &mut sort_func);
+// index and position of the first byte after which the end cannot be reached
+// anymore, found by removing the bytes in reverse order until start and end connect
+fn first_blocker(starting_map: &Map, blocks: &[Point]) -> Result<Option<(usize, Point)>, ByteError> {
+    let size = starting_map.get_size();
+    let index = |pos: (usize,usize)| pos.0*size.1 + pos.1;
 
-    if let Ok(i) = search_res {
-        vec.insert(i, elem);
-        //let distances: Vec<usize> = vec.iter().map(sort_func).collect();
-        //println!("After insert ok of {} at {}: {:?}", target_key, i, distances);
+    let cells = starting_map.byte_cells(blocks)?;
+    let mut map = starting_map.clone();
+    for &cell in &cells {
+        *map.get_cell_mut(cell).unwrap() = Cell::Wall;
     }
-    else if let Err(i) = search_res {
-        vec.insert(i, elem);
-        //let distances: Vec<usize> = vec.iter().map(sort_func).collect();
-        //println!("After insert err of {} at {}: {:?}", target_key, i, distances);
-    }
-}
 
-fn explore_base_paths(map: &Map) -> HashMap<((usize,usize),Direction), ExploreInfo> {
-    let mut infos: HashMap<((usize,usize),Direction), ExploreInfo> = HashMap::new();
-    let mut to_explore: VecDeque<_> = VecDeque::from( [(map.start, Direction::Right)] );
-    infos.insert((map.start, Direction::Right), ExploreInfo{
-        parents: vec![],
-        parent_action: vec![],
-        distance:0
-    });
-
-    let mut prev_distance = 0;
-
-    while !to_explore.is_empty() {
-        let current = to_explore.pop_front().unwrap();
-        let mut needs_sort = false;
-        /*
-        if current.0 == map.end {
-            break;
-        }
-        */
-        let current_distance = infos.get(&current).unwrap().distance;
-        //println!("Explore at distance {}", current_distance);
-        if prev_distance > current_distance {
-            println!("Explore at distance {} after {}", current_distance, prev_distance);
-            panic!();
-        }
-        prev_distance = current_distance;
-        // forward
-        let moved_forward = get_moved_position(current.0, map.get_size(), current.1);
-        if let Some(pos_forward) = moved_forward {
-            if *map.get_cell(moved_forward.unwrap()).unwrap() == Cell::Empty {
-                let entry = infos.entry((pos_forward, current.1));
-                let new_info = ExploreInfo{
-                    parents: vec![current],
-                    parent_action: vec![Action::Forward],
-                    distance: current_distance + COST_FORWARD,
-                };
-                match entry {
-                    Entry::Vacant(v) => {
-                        v.insert(new_info);
-                        insert_sorted_by(&mut to_explore, (pos_forward, current.1), |e| infos.get(e).unwrap().distance);
-                    },
-                    Entry::Occupied(mut o) => {
-                        if o.get().distance > new_info.distance {
-                            o.insert(new_info);
-                            needs_sort = true;
-                        } else if o.get().distance == new_info.distance {
-                            o.get_mut().parents.push(current);
-                            o.get_mut().parent_action.push(Action::Forward);
-                        }
-                    }
-                };
-            }
-        }
-        // left turn
-        {
-            let entry = infos.entry((current.0, current.1.turn_left()));
-            let new_info = ExploreInfo{
-                parents: vec![current],
-                parent_action: vec![Action::TurnLeft],
-                distance: current_distance + COST_TURN,
-            };
-            match entry {
-                Entry::Vacant(v) => {
-                    v.insert(new_info);
-                    insert_sorted_by(&mut to_explore, (current.0, current.1.turn_left()), |e| infos.get(e).unwrap().distance);
-                },
-                Entry::Occupied(mut o) => {
-                    if o.get().distance > new_info.distance {
-                        o.insert(new_info);
-                        needs_sort = true;
-                    } else if o.get().distance == new_info.distance && COST_TURN != 0 {
-                        o.get_mut().parents.push(current);
-                        o.get_mut().parent_action.push(Action::TurnLeft);
-                    }
+    let mut sets = DisjointSet::new(size.0*size.1);
+    let open_up = |map: &Map, sets: &mut DisjointSet, pos: (usize,usize)| {
+        for dir in [Direction::Up, Direction::Down, Direction::Left, Direction::Right] {
+            if let Some(next) = get_moved_position(pos, size, dir) {
+                if *map.get_cell(next).unwrap() == Cell::Empty {
+                    sets.union(index(pos), index(next));
                 }
-            };
-        }
-        // right turn
-        {
-            let entry = infos.entry((current.0, current.1.turn_right()));
-            let new_info = ExploreInfo{
-                parents: vec![current],
-                parent_action: vec![Action::TurnRight],
-                distance: current_distance + COST_TURN,
-            };
-            match entry {
-                Entry::Vacant(v) => {
-                    v.insert(new_info);
-                    insert_sorted_by(&mut to_explore, (current.0, current.1.turn_right()), |e| infos.get(e).unwrap().distance);
-                },
-                Entry::Occupied(mut o) => {
-                    if o.get().distance > new_info.distance {
-                        o.insert(new_info);
-                        needs_sort = true;
-                    } else if o.get().distance == new_info.distance && COST_TURN != 0 {
-                        o.get_mut().parents.push(current);
-                        o.get_mut().parent_action.push(Action::TurnRight);
-                    }
-                }
-            };
+            }
         }
-        if needs_sort {
-            to_explore.make_contiguous().sort_by_key(|e| infos.get(e).unwrap().distance);
+    };
+    for i in 0..size.0 {
+        for j in 0..size.1 {
+            if *map.get_cell((i,j)).unwrap() == Cell::Empty {
+                open_up(&map, &mut sets, (i,j));
+            }
         }
     }
 
-    infos
-}
-
-fn best_path_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> Vec<Action> {
-    // build backwards
-    let mut end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
-    end_vec.sort_by_key(|item| infos.get(item).unwrap().distance);
-    let mut reached = end_vec.first().cloned();
-    let mut res: Vec<Action> = vec![];
-    while reached.is_some() {
-        let pos = reached.unwrap();
-        let info = infos.get(&pos).unwrap();
-        reached = info.parents.first().cloned();
-        if let Some(act) = info.parent_action.first() {
-            res.push(*act);
-        }
+    let connected = |map: &Map, sets: &mut DisjointSet| {
+        *map.get_cell(map.start).unwrap() == Cell::Empty
+            && *map.get_cell(map.end).unwrap() == Cell::Empty
+            && sets.find(index(map.start)) == sets.find(index(map.end))
+    };
+    if connected(&map, &mut sets) {
+        return Ok(None);
     }
-    res.reverse();
-    res
-}
-
-fn best_path_pos_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> Vec<(usize,usize)> {
-    // build backwards
-    let mut end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
-    end_vec.sort_by_key(|item| infos.get(item).unwrap().distance);
-    let mut reached = end_vec.first().cloned();
-    let mut res: Vec<(usize,usize)> = vec![map.end];
-    while reached.is_some() {
-        let pos = reached.unwrap();
-        let info = infos.get(&pos).unwrap();
-        reached = info.parents.first().cloned();
-        if let Some(act) = info.parent_action.first() {
-            if *act == Action::Forward {
-                res.push(reached.unwrap().0);
+    for (k, &pos) in cells.iter().enumerate().rev() {
+        if starting_map.get_cell(pos) == Some(&Cell::Empty) {
+            *map.get_cell_mut(pos).unwrap() = Cell::Empty;
+            open_up(&map, &mut sets, pos);
+            if connected(&map, &mut sets) {
+                return Ok(Some((k, blocks[k])));
             }
         }
     }
-    res.reverse();
-    res
+    Ok(None)
 }
 
-fn first_blocker(starting_map: Map, blocks: &[(usize,usize)]) -> (usize,usize) {
-    let mut map = starting_map;
-    let shortest_info = explore_base_paths(&map);
-    let mut shortest_path_pos = best_path_pos_from_infos(&map, &shortest_info);
-    
-    let mut current_block = 0;
-    while current_block+1 < blocks.len() {
-        current_block += 1;
-        let block_pos = blocks[current_block];
-        //let prev_map = map.clone();
-        *map.get_cell_mut(block_pos).unwrap() = Cell::Wall;
-        if shortest_path_pos.contains(&block_pos) {
-            // block lies on best path: recompute
-            let shortest_info = explore_base_paths(&map);
-            if shortest_info.keys().filter(|(p,_)| *p==map.end).count() > 0 {
-                shortest_path_pos = best_path_pos_from_infos(&map, &shortest_info);
-            } else {
-                //println!("{} Map after last block at {:?}", map._repr(), block_pos);
-                //println!("{} Map before", prev_map._repr());
-                return block_pos;
+// fastest escape when the k-th byte (counting from 1) lands at time k, a cell
+// being unusable from the time its byte lands; staying in place is allowed
+fn escape_while_falling(map: &Map, blocks: &[Point]) -> Result<Option<usize>, ByteError> {
+    let size = map.get_size();
+    let mut fall_time: Vec<Vec<Option<usize>>> = vec![vec![None; size.1]; size.0];
+    for (k, cell) in map.byte_cells(blocks)?.into_iter().enumerate() {
+        fall_time[cell.0][cell.1] = Some(k+1);
+    }
+    let is_free = |pos: (usize,usize), time: usize| {
+        *map.get_cell(pos).unwrap() == Cell::Empty && fall_time[pos.0][pos.1].is_none_or(|t| time < t)
+    };
+
+    // positions that can be occupied at each time
+    let mut time = 0;
+    let mut reachable = vec![vec![false; size.1]; size.0];
+    if !is_free(map.start, 0) {
+        return Ok(None);
+    }
+    reachable[map.start.0][map.start.1] = true;
+    loop {
+        if reachable[map.end.0][map.end.1] {
+            return Ok(Some(time));
+        }
+        time += 1;
+        let mut next = vec![vec![false; size.1]; size.0];
+        for (i, row) in reachable.iter().enumerate() {
+            for (j, _) in row.iter().enumerate().filter(|(_, r)| **r) {
+                let moves = [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+                    .into_iter()
+                    .filter_map(|dir| get_moved_position((i,j), size, dir));
+                for pos in std::iter::once((i,j)).chain(moves) {
+                    if is_free(pos, time) {
+                        next[pos.0][pos.1] = true;
+                    }
+                }
             }
         }
+        // once every byte fell the map is static: no change means no escape
+        if (next == reachable && time > blocks.len()) || next.iter().flatten().all(|r| !r) {
+            return Ok(None);
+        }
+        reachable = next;
     }
-    return (555,555);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::lcg::Lcg;
 
     #[test]
     fn example() {
         let mut laby = Map::empty(7, 7);
-        for (i,j) in parse_pos("5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0")[..12].iter() {
-            *laby.get_cell_mut((*j,*i)).unwrap() = Cell::Wall;
-        }
+        let blocks = parse_pos("5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0").unwrap();
+        laby.drop_bytes(&blocks[..12]).unwrap();
 
         println!("{:?}", laby);
 
-        let shortest_info = explore_base_paths(&laby);
+        let shortest_info = explore_base_paths(&laby, &WALK_COSTS);
         let path = best_path_from_infos(&laby, &shortest_info);
 
         assert_eq!(path.iter().filter(|a| **a == Action::Forward).count(), 22);
@@ -378,10 +299,124 @@ mod tests {
     #[test]
     fn example_block() {
         let laby = Map::empty(7, 7);
-        let blocks = parse_pos("5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0");
+        let blocks = parse_pos("5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0").unwrap();
+
+        assert_eq!(first_blocker(&laby, &blocks), Ok(Some((20, Point{x: 6, y: 1}))));
+        assert_eq!(first_blocker(&laby, &blocks[..20]), Ok(None));
+    }
+
+    #[test]
+    fn orientation() {
+        // 5 wide and 3 tall: the bytes wall off the column x=3, then free the middle again
+        let laby = Map::empty(3, 5);
+        let blocks = parse_pos("3,0\n3,1\n1,1\n3,2").unwrap();
+
+        let mut map = laby.clone();
+        map.drop_bytes(&blocks[..3]).unwrap();
+        assert_eq!(map._repr(), "...#.\n.#.#.\n.....\n");
+        let shortest_info = explore_base_paths(&map, &WALK_COSTS);
+        let path = best_path_from_infos(&map, &shortest_info);
+        assert_eq!(path.iter().filter(|a| **a == Action::Forward).count(), 6);
+
+        assert_eq!(first_blocker(&laby, &blocks), Ok(Some((3, Point{x: 3, y: 2}))));
+        map.drop_bytes(&blocks[3..]).unwrap();
+        assert!(!explore_base_paths(&map, &WALK_COSTS).keys().any(|(p,_)| *p == map.end));
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let laby = Map::empty(3, 5);
+        assert_eq!(parse_pos("1,2\n3;4"), Err(ByteError::Parse{line: 1}));
+        assert_eq!(parse_pos("1,x"), Err(ByteError::Parse{line: 0}));
+
+        // fits the map only when transposed
+        let blocks = parse_pos("1,1\n2,4").unwrap();
+        assert_eq!(laby.clone().drop_bytes(&blocks), Err(ByteError::OutOfBounds{index: 1, pos: Point{x: 2, y: 4}}));
+        assert_eq!(first_blocker(&laby, &blocks), Err(ByteError::OutOfBounds{index: 1, pos: Point{x: 2, y: 4}}));
+
+        let blocks = parse_pos("1,1\n4,2\n1,1").unwrap();
+        let err = laby.clone().drop_bytes(&blocks).unwrap_err();
+        assert_eq!(err, ByteError::Duplicate{index: 2, first: 0, pos: Point{x: 1, y: 1}});
+        assert_eq!(err.to_string(), "byte 2 at 1,1 falls where byte 0 already fell");
+    }
+
+    #[test]
+    fn example_falling() {
+        let laby = Map::empty(7, 7);
+        let blocks = parse_pos("5,4\n4,2\n4,5\n3,0\n2,1\n6,3\n2,4\n1,5\n0,6\n3,3\n2,6\n5,1\n1,2\n5,5\n2,5\n6,5\n1,4\n0,4\n6,4\n1,1\n6,1\n1,0\n0,5\n1,6\n2,0").unwrap();
+
+        // all the bytes block the exit, but not before the fastest runner gets there
+        assert_eq!(escape_while_falling(&laby, &blocks), Ok(Some(12)));
+        assert_eq!(escape_while_falling(&laby, &[]), Ok(Some(12)));
+
+        // the exit is covered before anyone can reach it
+        let blocks = parse_pos("6,6").unwrap();
+        assert_eq!(escape_while_falling(&laby, &blocks), Ok(None));
+    }
+
+    #[test]
+    fn falling_matches_search() {
+        // explicit (position, time) search, without the static map shortcut
+        fn reference(laby: &Map, blocks: &[Point]) -> Option<usize> {
+            let cells = laby.byte_cells(blocks).unwrap();
+            let blocked = |pos: (usize,usize), time: usize| cells[..time.min(cells.len())].contains(&pos);
+            let mut front = vec![laby.start];
+            for time in 0..60 {
+                if front.contains(&laby.end) {
+                    return Some(time);
+                }
+                let mut next = vec![];
+                for pos in front {
+                    let moves = [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+                        .into_iter()
+                        .filter_map(|dir| get_moved_position(pos, laby.get_size(), dir));
+                    for next_pos in std::iter::once(pos).chain(moves) {
+                        if !blocked(next_pos, time+1) && !next.contains(&next_pos) {
+                            next.push(next_pos);
+                        }
+                    }
+                }
+                front = next;
+            }
+            None
+        }
 
-        let first = first_blocker(laby, &blocks);
+        let laby = Map::empty(5, 6);
+        let mut rng = Lcg(41);
+        for _ in 0..40 {
+            let mut blocks: Vec<Point> = vec![];
+            while blocks.len() < 20 {
+                let pos = Point{x: (rng.next() % 6) as usize, y: (rng.next() % 5) as usize};
+                if !blocks.contains(&pos) && pos != (Point{x: 0, y: 0}) {
+                    blocks.push(pos);
+                }
+            }
+            assert_eq!(escape_while_falling(&laby, &blocks), Ok(reference(&laby, &blocks)));
+        }
+    }
 
-        assert_eq!(first, (6,1));
+    #[test]
+    fn blocker_matches_search() {
+        // whether the exit can be reached after the first `count` bytes, by a full search
+        fn reachable(laby: &Map, blocks: &[Point], count: usize) -> bool {
+            let mut map = laby.clone();
+            map.drop_bytes(&blocks[..count]).unwrap();
+            *map.get_cell(map.start).unwrap() == Cell::Empty
+                && explore_base_paths(&map, &WALK_COSTS).keys().any(|(p,_)| *p == map.end)
+        }
+
+        let laby = Map::empty(5, 8);
+        let mut rng = Lcg(18);
+        for _ in 0..20 {
+            let mut blocks: Vec<Point> = vec![];
+            while blocks.len() < 30 {
+                let pos = Point{x: (rng.next() % 8) as usize, y: (rng.next() % 5) as usize};
+                if !blocks.contains(&pos) {
+                    blocks.push(pos);
+                }
+            }
+            let expected = (1..=blocks.len()).find(|&count| !reachable(&laby, &blocks, count)).map(|count| count-1);
+            assert_eq!(first_blocker(&laby, &blocks), Ok(expected.map(|k| (k, blocks[k]))));
+        }
     }
 }
@@ -1,8 +1,11 @@
-use core::hash;
+#[cfg(test)]
+#[path = "common/lcg.rs"]
+mod lcg;
+
 use std::fs;
 use std::env;
 use std::collections::HashMap;
-use std::sync::atomic::AtomicUsize;
+use std::num::NonZeroU64;
 //use regex;
 
 fn main() {
@@ -16,62 +19,180 @@ fn main() {
     let available = parse_available(contents_it.next().unwrap());
     let validator = Validator::from(&available);
     let patterns = contents_it.next().unwrap();
-    let possible = patterns.split_terminator('\n')
-        .filter(|s| validator.is_match(s))
-        .count();
+    let designs: Vec<&str> = patterns.split_terminator('\n').collect();
 
-    let ways: usize = patterns.split_terminator('\n')
-        .map(|s| validator.count_arrangements(s))
-        .sum();
+    let mut options = args[2..].iter()
+        .map(|s| s.as_str())
+        .filter(|&s| s != "--aho-corasick");
+    let use_aho_corasick = args[2..].iter().any(|s| s == "--aho-corasick");
+
+    let (possible, ways) = if use_aho_corasick {
+        solve(&AhoCorasick::from(&available), &designs)
+    } else {
+        solve(&validator, &designs)
+    };
 
     println!("Result: {}", possible);
     println!("Result2: {}", ways);
+
+    match options.next() {
+        Some("--show") => show_arrangements(&validator, &designs),
+        Some("--analyse") => show_analysis(&available, &designs),
+        Some("--modulo") => {
+            let modulus: NonZeroU64 = options.next().unwrap().parse()
+                .expect("Modulus should be a positive integer");
+            let ways = if use_aho_corasick {
+                total(&AhoCorasick::from(&available), &designs, &ModCount(modulus))
+            } else {
+                total(&validator, &designs, &ModCount(modulus))
+            };
+            println!("Result2 modulo {}: {}", modulus, ways);
+        }
+        _ => {}
+    }
+}
+
+fn solve<M: Matcher>(matcher: &M, designs: &[&str]) -> (usize, String) {
+    let possible = designs.iter()
+        .filter(|s| matcher.count_arrangements_with(s, &Reachable))
+        .count();
+    (possible, total_arrangements(matcher, designs))
+}
+
+fn total<M: Matcher, A: Accumulator>(matcher: &M, designs: &[&str], acc: &A) -> A::Value {
+    let mut res = acc.zero();
+    for design in designs {
+        acc.add(&mut res, &matcher.count_arrangements_with(design, acc));
+    }
+    res
+}
+
+// total over all designs, with the smallest integer type that holds it
+fn total_arrangements<M: Matcher>(matcher: &M, designs: &[&str]) -> String {
+    if let Some(ways) = total(matcher, designs, &CheckedU64) {
+        ways.to_string()
+    } else if let Some(ways) = total(matcher, designs, &CheckedU128) {
+        ways.to_string()
+    } else {
+        total(matcher, designs, &BigCount).to_string()
+    }
+}
+
+fn show_arrangements(validator: &Validator, designs: &[&str]) {
+    for &design in designs {
+        if let Some(first) = validator.first_arrangement(design) {
+            println!("{}: first {}, fewest {}, most {}",
+                design,
+                first.join(","),
+                validator.fewest_towels_arrangement(design).unwrap().join(","),
+                validator.most_towels_arrangement(design).unwrap().join(","));
+        } else {
+            println!("{}: impossible", design);
+        }
+    }
 }
 
-static COMPLETION_DICT_ID: AtomicUsize = AtomicUsize::new(0);
+fn show_analysis(available: &[&str], designs: &[&str]) {
+    let analysis = analyse_towels(available, designs);
+    println!("Redundant towels ({}): {}", analysis.redundant.len(), analysis.redundant.join(", "));
+    println!("Minimal towel set ({}): {}", analysis.minimal.len(), analysis.minimal.join(", "));
+    let Some(usage) = analysis.usage else {
+        println!("Towel usage does not fit in 64 bits");
+        return;
+    };
+    let mut usage: Vec<(&str,u64)> = usage.into_iter().collect();
+    usage.sort_by_key(|&(towel,count)| (std::cmp::Reverse(count), towel));
+    for (towel, count) in usage {
+        println!("{}: used {} times", towel, count);
+    }
+}
+
+type NodeId = usize;
 
+const ROOT: NodeId = 0;
+
+// trie stored in flat arrays: node `n` has its child for the `k`-th
+// symbol of the alphabet at `children[n*alphabet.len() + k]`
 #[derive(Debug)]
-struct CompletionDict<T>
-where T: hash::Hash
-{
-    is_complete: bool,
-    id: usize,
-    possibilities: HashMap<T,Box<CompletionDict<T>>>,
+struct CompletionDict<T> {
+    alphabet: Vec<T>,
+    is_complete: Vec<bool>,
+    children: Vec<Option<NodeId>>,
 }
 
 impl<T> CompletionDict<T>
-where T: hash::Hash,
-      T: std::cmp::Eq
+where T: std::cmp::Eq + Copy
 {
     fn new() -> CompletionDict<T> {
         CompletionDict{
-            possibilities: HashMap::new(),
-            is_complete: false,
-            id: COMPLETION_DICT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            alphabet: vec![],
+            is_complete: vec![false],
+            children: vec![],
         }
     }
 
+    fn node_count(&self) -> usize {
+        self.is_complete.len()
+    }
+
+    fn symbol(&self, item: &T) -> Option<usize> {
+        self.alphabet.iter().position(|s| s == item)
+    }
+
+    fn add_symbol(&mut self, item: T) -> usize {
+        // widen every node's child table by one column
+        let width = self.alphabet.len();
+        let mut children = Vec::with_capacity(self.node_count() * (width+1));
+        for node in 0..self.node_count() {
+            children.extend_from_slice(&self.children[node*width..(node+1)*width]);
+            children.push(None);
+        }
+        self.children = children;
+        self.alphabet.push(item);
+        width
+    }
+
+    fn add_node(&mut self) -> NodeId {
+        self.is_complete.push(false);
+        self.children.extend(std::iter::repeat_n(None, self.alphabet.len()));
+        self.node_count() - 1
+    }
+
     fn insert(&mut self, it: &mut dyn Iterator<Item=T>) {
-        if let Some(elem) = it.next() {
-            self.possibilities
-                .entry(elem)
-                .or_insert(Box::new(CompletionDict::new()))
-                .insert(it);
-        } else {
-            self.is_complete = true;
+        let mut node = ROOT;
+        for elem in it {
+            let symbol = match self.symbol(&elem) {
+                Some(symbol) => symbol,
+                None => self.add_symbol(elem),
+            };
+            let slot = node*self.alphabet.len() + symbol;
+            node = match self.children[slot] {
+                Some(next) => next,
+                None => {
+                    let next = self.add_node();
+                    self.children[node*self.alphabet.len() + symbol] = Some(next);
+                    next
+                }
+            };
         }
+        self.is_complete[node] = true;
     }
 
-    fn get(&self, item: &T) -> Option<&CompletionDict<T>> {
-        self.possibilities.get(item).map(|boxed| boxed.as_ref())
+    fn get(&self, node: NodeId, item: &T) -> Option<NodeId> {
+        let symbol = self.symbol(item)?;
+        self.children[node*self.alphabet.len() + symbol]
     }
-}
 
-impl<T> PartialEq for CompletionDict<T>
-where T: hash::Hash
-{
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+    fn is_complete(&self, node: NodeId) -> bool {
+        self.is_complete[node]
+    }
+
+    fn children(&self, node: NodeId) -> impl Iterator<Item=(usize,NodeId)> + '_ {
+        let width = self.alphabet.len();
+        self.children[node*width..(node+1)*width]
+            .iter()
+            .enumerate()
+            .filter_map(|(symbol, child)| child.map(|child| (symbol,child)))
     }
 }
 
@@ -97,38 +218,474 @@ impl From<&Vec<&str>> for Validator {
 
 impl Validator {
     fn is_match(&self, s: &str) -> bool {
-        let arr= self.count_arrangements(s);
-        //println!("Test match {} gives {}", s, arr);
-        arr > 0
+        self.count_arrangements_with(s, &Reachable)
     }
 
-    fn count_arrangements(&self, s: &str) -> usize {
+    // None when the count does not fit in 64 bits
+    fn count_arrangements(&self, s: &str) -> Option<u64> {
+        self.count_arrangements_with(s, &CheckedU64)
+    }
+}
+
+// counts the arrangements of a design, whatever the matching strategy
+trait Matcher {
+    fn count_arrangements_with<A: Accumulator>(&self, s: &str, acc: &A) -> A::Value;
+}
+
+impl Matcher for Validator {
+    fn count_arrangements_with<A: Accumulator>(&self, s: &str, acc: &A) -> A::Value {
         // number of ways to validate s
-        let mut heads: HashMap<usize,(&CompletionDict<char>,usize)> = HashMap::new();
-        heads.insert(self.completions.id, (&self.completions,1));
+        let dict = &self.completions;
+        let mut heads: Vec<(NodeId,A::Value)> = vec![(ROOT,acc.one())];
+        let mut new_counts: Vec<Option<A::Value>> = vec![None; dict.node_count()];
         for c in s.chars() {
-            //println!("Match step {} : {:?}", c, heads.keys());
-            let mut new_heads = HashMap::new();
-            for (head, path_count) in heads.values() {
-                if let Some(next) = head.get(&c) {
-                    if next.is_complete {
-                        new_heads.entry(self.completions.id).or_insert((&self.completions,0)).1 += path_count;
+            let mut new_heads = vec![];
+            for (head, path_count) in &heads {
+                if let Some(next) = dict.get(*head, &c) {
+                    if dict.is_complete(next) {
+                        add_count(acc, &mut new_counts[ROOT], path_count, &mut new_heads, ROOT);
                     }
-                    new_heads.entry(next.id).or_insert((next,0)).1 += path_count;
+                    add_count(acc, &mut new_counts[next], path_count, &mut new_heads, next);
                 }
             }
-            heads = new_heads;
+            heads = new_heads.into_iter()
+                .map(|node| (node, new_counts[node].take().unwrap()))
+                .collect();
         }
-        heads.values()
-            .filter(|(d,_)| *d==&self.completions)
-            .map(|(_,count)| *count)
+        heads.into_iter()
+            .filter(|(node,_)| *node == ROOT)
+            .map(|(_,count)| count)
             .next()
-            .unwrap_or_default()
+            .unwrap_or_else(|| acc.zero())
+    }
+}
+
+fn add_count<A: Accumulator>(acc: &A, count: &mut Option<A::Value>, path_count: &A::Value, heads: &mut Vec<NodeId>, node: NodeId) {
+    match count {
+        Some(count) => acc.add(count, path_count),
+        None => {
+            heads.push(node);
+            *count = Some(path_count.clone());
+        }
     }
 }
 
+// how path counts are summed while matching
+trait Accumulator {
+    type Value: Clone;
+
+    fn zero(&self) -> Self::Value;
+    fn one(&self) -> Self::Value;
+    fn add(&self, value: &mut Self::Value, other: &Self::Value);
+}
+
+// only whether there is an arrangement at all
+struct Reachable;
+
+impl Accumulator for Reachable {
+    type Value = bool;
+
+    fn zero(&self) -> bool { false }
+    fn one(&self) -> bool { true }
+    fn add(&self, value: &mut bool, other: &bool) {
+        *value |= *other;
+    }
+}
+
+// None once the count overflowed
+struct CheckedU64;
+
+impl Accumulator for CheckedU64 {
+    type Value = Option<u64>;
+
+    fn zero(&self) -> Option<u64> { Some(0) }
+    fn one(&self) -> Option<u64> { Some(1) }
+    fn add(&self, value: &mut Option<u64>, other: &Option<u64>) {
+        *value = value.zip(*other).and_then(|(a,b)| a.checked_add(b));
+    }
+}
+
+// None once the count overflowed
+struct CheckedU128;
+
+impl Accumulator for CheckedU128 {
+    type Value = Option<u128>;
+
+    fn zero(&self) -> Option<u128> { Some(0) }
+    fn one(&self) -> Option<u128> { Some(1) }
+    fn add(&self, value: &mut Option<u128>, other: &Option<u128>) {
+        *value = value.zip(*other).and_then(|(a,b)| a.checked_add(b));
+    }
+}
+
+struct BigCount;
+
+impl Accumulator for BigCount {
+    type Value = BigUint;
+
+    fn zero(&self) -> BigUint { BigUint::from(0) }
+    fn one(&self) -> BigUint { BigUint::from(1) }
+    fn add(&self, value: &mut BigUint, other: &BigUint) {
+        *value += other;
+    }
+}
+
+// counts modulo the given value (usually a prime), which cannot be zero
+struct ModCount(NonZeroU64);
+
+impl Accumulator for ModCount {
+    type Value = u64;
+
+    fn zero(&self) -> u64 { 0 }
+    fn one(&self) -> u64 { 1 % self.0.get() }
+    fn add(&self, value: &mut u64, other: &u64) {
+        *value = ((*value as u128 + *other as u128) % self.0.get() as u128) as u64;
+    }
+}
+
+// unsigned integer of any size, only supporting what counting needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BigUint {
+    // little-endian base 2^32 digits, without trailing zeros
+    digits: Vec<u32>,
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        let mut res = BigUint{digits: vec![value as u32, (value >> 32) as u32]};
+        res.normalize();
+        res
+    }
+}
+
+impl BigUint {
+    fn normalize(&mut self) {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+    }
+
+    // divides in place, returning the remainder
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for digit in self.digits.iter_mut().rev() {
+            let current = (rem << 32) | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            rem = current % divisor as u64;
+        }
+        self.normalize();
+        rem as u32
+    }
+}
+
+impl std::ops::AddAssign<&BigUint> for BigUint {
+    fn add_assign(&mut self, other: &BigUint) {
+        if self.digits.len() < other.digits.len() {
+            self.digits.resize(other.digits.len(), 0);
+        }
+        let mut carry = 0u64;
+        for (i, digit) in self.digits.iter_mut().enumerate() {
+            let sum = *digit as u64 + *other.digits.get(i).unwrap_or(&0) as u64 + carry;
+            *digit = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+}
+
+impl std::fmt::Display for BigUint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // chunks of 9 decimal digits, least significant first
+        let mut value = self.clone();
+        let mut chunks = vec![];
+        while !value.digits.is_empty() {
+            chunks.push(value.div_rem_small(1_000_000_000));
+        }
+        match chunks.pop() {
+            None => write!(f, "0"),
+            Some(first) => {
+                write!(f, "{}", first)?;
+                for chunk in chunks.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// lazily walks every decomposition of a design into towels, in
+// lexicographic order of the towel sequence
+struct Arrangements<'a> {
+    design: &'a str,
+    bounds: Vec<usize>,
+    choices: Vec<Vec<usize>>,
+    stack: Vec<(usize,usize)>,
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let (pos, choice) = *frame;
+            if pos == self.bounds.len()-1 {
+                let res = self.stack.windows(2)
+                    .map(|w| &self.design[self.bounds[w[0].0]..self.bounds[w[1].0]])
+                    .collect();
+                self.stack.pop();
+                return Some(res);
+            }
+            if let Some(&next) = self.choices[pos].get(choice) {
+                frame.1 += 1;
+                self.stack.push((next,0));
+            } else {
+                self.stack.pop();
+            }
+        }
+    }
+}
+
+impl Validator {
+    // char boundaries of s, and for each boundary the boundaries reachable by
+    // one towel from which the rest of s can still be completed
+    fn towel_ends(&self, s: &str) -> (Vec<usize>, Vec<Vec<usize>>) {
+        let dict = &self.completions;
+        let chars: Vec<char> = s.chars().collect();
+        let bounds: Vec<usize> = s.char_indices().map(|(i,_)| i).chain(std::iter::once(s.len())).collect();
+        let mut choices = vec![vec![]; bounds.len()];
+        let mut completable = vec![false; bounds.len()];
+        completable[chars.len()] = true;
+        for start in (0..chars.len()).rev() {
+            let mut node = ROOT;
+            for (end, c) in chars.iter().enumerate().skip(start).map(|(i,c)| (i+1,c)) {
+                match dict.get(node, c) {
+                    Some(next) => node = next,
+                    None => break,
+                }
+                if dict.is_complete(node) && completable[end] {
+                    choices[start].push(end);
+                }
+            }
+            completable[start] = !choices[start].is_empty();
+        }
+        (bounds, choices)
+    }
+
+    fn arrangements<'a>(&self, s: &'a str) -> Arrangements<'a> {
+        let (bounds, choices) = self.towel_ends(s);
+        // towels starting at the same place are prefixes of each other, so
+        // shorter ones (first in `choices`) are lexicographically smaller
+        let stack = if bounds.len() == 1 || !choices[0].is_empty() {
+            vec![(0,0)]
+        } else {
+            vec![]
+        };
+        Arrangements{design: s, bounds, choices, stack}
+    }
+
+    fn first_arrangement<'a>(&self, s: &'a str) -> Option<Vec<&'a str>> {
+        self.arrangements(s).next()
+    }
+
+    fn fewest_towels_arrangement<'a>(&self, s: &'a str) -> Option<Vec<&'a str>> {
+        self.extreme_arrangement(s, |a,b| a < b)
+    }
+
+    fn most_towels_arrangement<'a>(&self, s: &'a str) -> Option<Vec<&'a str>> {
+        self.extreme_arrangement(s, |a,b| a > b)
+    }
+
+    // arrangement with the best towel count according to `better`,
+    // the lexicographically first one among ties
+    fn extreme_arrangement<'a>(&self, s: &'a str, better: fn(usize,usize) -> bool) -> Option<Vec<&'a str>> {
+        let (bounds, choices) = self.towel_ends(s);
+        let end = bounds.len()-1;
+        let mut best: Vec<Option<(usize,usize)>> = vec![None; bounds.len()];
+        best[end] = Some((0,end));
+        for pos in (0..end).rev() {
+            for &next in &choices[pos] {
+                let count = best[next].unwrap().0 + 1;
+                if best[pos].is_none_or(|(current,_)| better(count, current)) {
+                    best[pos] = Some((count,next));
+                }
+            }
+        }
+        best[0]?;
+        let mut res = vec![];
+        let mut pos = 0;
+        while pos != end {
+            let next = best[pos].unwrap().1;
+            res.push(&s[bounds[pos]..bounds[next]]);
+            pos = next;
+        }
+        Some(res)
+    }
+}
+
+impl Validator {
+    // for each towel, how many times it appears summed over all arrangements of s,
+    // None when a count does not fit in 64 bits
+    fn towel_usage<'a>(&self, s: &'a str) -> Option<HashMap<&'a str,u64>> {
+        let (bounds, choices) = self.towel_ends(s);
+        let end = bounds.len()-1;
+        let mut ways_to = vec![0u64; bounds.len()];
+        let mut ways_from = vec![0u64; bounds.len()];
+        ways_from[end] = 1;
+        for pos in (0..end).rev() {
+            for &next in &choices[pos] {
+                ways_from[pos] = ways_from[pos].checked_add(ways_from[next])?;
+            }
+        }
+        if ways_from[0] > 0 {
+            ways_to[0] = 1;
+        }
+        let mut usage: HashMap<&str,u64> = HashMap::new();
+        for pos in 0..end {
+            // dead ends are skipped, so ways_to never exceeds the arrangement count
+            for &next in choices[pos].iter().filter(|&&next| ways_from[next] > 0) {
+                ways_to[next] = ways_to[next].checked_add(ways_to[pos])?;
+                let count = usage.entry(&s[bounds[pos]..bounds[next]]).or_default();
+                *count = ways_to[pos].checked_mul(ways_from[next]).and_then(|uses| count.checked_add(uses))?;
+            }
+        }
+        Some(usage)
+    }
+}
+
+struct TowelAnalysis<'a> {
+    // towels that can be built from other available towels
+    redundant: Vec<&'a str>,
+    // subset of the towels from which no towel can be removed without
+    // making one of the designs impossible
+    minimal: Vec<&'a str>,
+    // None when a count does not fit in 64 bits
+    usage: Option<HashMap<&'a str,u64>>,
+}
+
+fn analyse_towels<'a>(available: &[&'a str], designs: &[&str]) -> TowelAnalysis<'a> {
+    let validator = Validator::from(available);
+
+    // a towel always matches itself, any other arrangement uses strictly shorter towels
+    let redundant: Vec<&str> = available.iter()
+        .copied()
+        .filter(|towel| validator.count_arrangements(towel).is_none_or(|ways| ways > 1))
+        .collect();
+
+    // the non-redundant towels can rebuild every redundant one, so start from
+    // them and drop the ones the designs do not need, longest first
+    let possible: Vec<&str> = designs.iter()
+        .copied()
+        .filter(|design| validator.is_match(design))
+        .collect();
+    let mut minimal: Vec<&str> = available.iter()
+        .copied()
+        .filter(|towel| !redundant.contains(towel))
+        .collect();
+    minimal.sort_by_key(|towel| std::cmp::Reverse(towel.len()));
+    let mut i = 0;
+    while i < minimal.len() {
+        let mut candidate = minimal.clone();
+        candidate.remove(i);
+        let reduced = Validator::from(&candidate);
+        if possible.iter().all(|design| reduced.is_match(design)) {
+            minimal = candidate;
+        } else {
+            i += 1;
+        }
+    }
+
+    let usage = designs.iter().try_fold(
+        available.iter().map(|&towel| (towel,0)).collect::<HashMap<&str,u64>>(),
+        |mut usage, design| {
+            for (towel, count) in validator.towel_usage(design)? {
+                let total = usage.get_mut(towel).unwrap();
+                *total = total.checked_add(count)?;
+            }
+            Some(usage)
+        });
+
+    TowelAnalysis{redundant, minimal, usage}
+}
+
+// automaton matching every towel in a single pass over a design: the trie
+// completed with failure transitions to the longest suffix that is a prefix
+struct AhoCorasick {
+    dict: CompletionDict<char>,
+    // full transition table, same layout as `dict.children`
+    transitions: Vec<NodeId>,
+    // lengths of the towels ending at each node, through failure links
+    outputs: Vec<Vec<usize>>,
+}
+
+impl From<&[&str]> for AhoCorasick {
+    fn from(value: &[&str]) -> Self {
+        let mut dict = CompletionDict::new();
+        for &item in value {
+            dict.insert(&mut item.chars());
+        }
+        let width = dict.alphabet.len();
+        let mut transitions = vec![ROOT; dict.node_count()*width];
+        let mut outputs = vec![vec![]; dict.node_count()];
+        let mut depth = vec![0; dict.node_count()];
+        let mut fail = vec![ROOT; dict.node_count()];
+
+        // breadth first, so failure targets are complete before they are used
+        let mut queue = std::collections::VecDeque::from([ROOT]);
+        while let Some(node) = queue.pop_front() {
+            // an empty towel completes the root, but matches nothing
+            if dict.is_complete(node) && node != ROOT {
+                outputs[node].push(depth[node]);
+            }
+            let inherited = outputs[fail[node]].clone();
+            outputs[node].extend(inherited);
+            for symbol in 0..width {
+                transitions[node*width + symbol] = transitions[fail[node]*width + symbol];
+            }
+            for (symbol, child) in dict.children(node) {
+                depth[child] = depth[node] + 1;
+                fail[child] = if node == ROOT { ROOT } else { transitions[fail[node]*width + symbol] };
+                transitions[node*width + symbol] = child;
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick{dict, transitions, outputs}
+    }
+}
+
+impl From<&Vec<&str>> for AhoCorasick {
+    fn from(value: &Vec<&str>) -> Self {
+        Self::from(&value[..])
+    }
+}
+
+impl Matcher for AhoCorasick {
+    fn count_arrangements_with<A: Accumulator>(&self, s: &str, acc: &A) -> A::Value {
+        // ways[i]: number of arrangements of the first i chars
+        let mut ways = vec![acc.one()];
+        let mut node = ROOT;
+        for (i, c) in s.chars().enumerate() {
+            node = match self.dict.symbol(&c) {
+                Some(symbol) => self.transitions[node*self.dict.alphabet.len() + symbol],
+                None => ROOT,
+            };
+            let mut count = acc.zero();
+            for &len in &self.outputs[node] {
+                acc.add(&mut count, &ways[i+1-len]);
+            }
+            ways.push(count);
+        }
+        ways.pop().unwrap()
+    }
+}
+
+// an empty towel covers nothing, so it is dropped
 fn parse_available(s: &str) -> Vec<&str>{
-    s.split(", ").collect()
+    s.split(", ").filter(|towel| !towel.is_empty()).collect()
 }
 
 /*
@@ -142,6 +699,7 @@ fn build_validator_re(patterns: &[&str]) -> regex::Regex {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::lcg::Lcg;
 
     #[test]
     fn example() {
@@ -164,10 +722,307 @@ mod tests {
 
         println!("{:?}", validator.completions);
 
-        let ways: Vec<usize> = "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n')
-            .map(|s| validator.count_arrangements(s))
+        let ways: Vec<u64> = "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n')
+            .map(|s| validator.count_arrangements(s).unwrap())
             .collect();
 
         assert_eq!(ways, vec![2, 1, 4, 6, 0, 1, 2, 0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn example_arrangements() {
+        let available = parse_available("r, wr, b, g, bwu, rb, gb, br");
+        let validator = Validator::from(&available);
+
+        let all: Vec<Vec<&str>> = validator.arrangements("gbbr").collect();
+        assert_eq!(all, vec![
+            vec!["g", "b", "b", "r"],
+            vec!["g", "b", "br"],
+            vec!["gb", "b", "r"],
+            vec!["gb", "br"],
+        ]);
+
+        for design in "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n') {
+            let arrangements: Vec<Vec<&str>> = validator.arrangements(design).collect();
+            assert_eq!(Some(arrangements.len() as u64), validator.count_arrangements(design));
+            for arrangement in &arrangements {
+                assert_eq!(arrangement.concat(), design);
+                assert!(arrangement.iter().all(|towel| available.contains(towel)));
+            }
+            let mut sorted = arrangements.clone();
+            sorted.sort();
+            assert_eq!(arrangements, sorted);
+        }
+
+        assert_eq!(validator.first_arrangement("rrbgbr"), Some(vec!["r", "r", "b", "g", "b", "r"]));
+        assert_eq!(validator.fewest_towels_arrangement("rrbgbr"), Some(vec!["r", "rb", "g", "br"]));
+        assert_eq!(validator.most_towels_arrangement("rrbgbr"), Some(vec!["r", "r", "b", "g", "b", "r"]));
+        assert_eq!(validator.fewest_towels_arrangement("bwurrg"), Some(vec!["bwu", "r", "r", "g"]));
+        assert_eq!(validator.first_arrangement("ubwu"), None);
+        assert_eq!(validator.most_towels_arrangement("bbrgwb"), None);
+    }
+
+    #[test]
+    fn example_analysis() {
+        let available = parse_available("r, wr, b, g, bwu, rb, gb, br");
+        let designs: Vec<&str> = "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n').collect();
+        let analysis = analyse_towels(&available, &designs);
+
+        assert_eq!(analysis.redundant, vec!["rb", "gb", "br"]);
+        let mut minimal = analysis.minimal.clone();
+        minimal.sort();
+        assert_eq!(minimal, vec!["b", "bwu", "g", "r", "wr"]);
+        let reduced = Validator::from(&analysis.minimal);
+        for design in &designs {
+            assert_eq!(reduced.is_match(design), Validator::from(&available).is_match(design));
+        }
+
+        // gbbr: g,b,b,r  g,b,br  gb,b,r  gb,br
+        let usage = Validator::from(&available).towel_usage("gbbr").unwrap();
+        assert_eq!(usage["g"], 2);
+        assert_eq!(usage["b"], 4);
+        assert_eq!(usage["r"], 2);
+        assert_eq!(usage["gb"], 2);
+        assert_eq!(usage["br"], 2);
+        assert_eq!(usage.get("rb"), None);
+
+        let total = designs.iter()
+            .flat_map(|design| Validator::from(&available).arrangements(design).collect::<Vec<_>>())
+            .flatten()
+            .filter(|&towel| towel == "r")
+            .count();
+        let usage = analysis.usage.unwrap();
+        assert_eq!(usage["r"], total as u64);
+        assert_eq!(usage["rb"], 3);
+    }
+
+    #[test]
+    fn example_accumulators() {
+        let available = parse_available("r, wr, b, g, bwu, rb, gb, br");
+        let validator = Validator::from(&available);
+        let designs: Vec<&str> = "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n').collect();
+
+        for design in &designs {
+            let ways = validator.count_arrangements(design).unwrap();
+            assert_eq!(validator.count_arrangements_with(design, &CheckedU128), Some(ways as u128));
+            assert_eq!(validator.count_arrangements_with(design, &BigCount), BigUint::from(ways));
+            assert_eq!(validator.count_arrangements_with(design, &ModCount(NonZeroU64::new(5).unwrap())), ways % 5);
+        }
+        assert_eq!(total_arrangements(&validator, &designs), "16");
+    }
+
+    #[test]
+    fn overflowing_counts() {
+        // arrangements of n single towels from {r, rr} follow the Fibonacci sequence
+        let available = parse_available("r, rr");
+        let validator = Validator::from(&available);
+
+        let design = "r".repeat(90);
+        let fib90 = 4660046610375530309u64;
+        assert_eq!(validator.count_arrangements_with(&design, &CheckedU64), Some(fib90));
+
+        let design = "r".repeat(100);
+        let fib100 = 573147844013817084101u128;
+        assert_eq!(validator.count_arrangements_with(&design, &CheckedU64), None);
+        assert_eq!(validator.count_arrangements_with(&design, &CheckedU128), Some(fib100));
+        assert_eq!(validator.count_arrangements_with(&design, &BigCount).to_string(), fib100.to_string());
+        assert_eq!(validator.count_arrangements_with(&design, &ModCount(NonZeroU64::new(1_000_000_007).unwrap())), (fib100 % 1_000_000_007) as u64);
+
+        let design = "r".repeat(300);
+        assert_eq!(validator.count_arrangements_with(&design, &CheckedU128), None);
+        assert_eq!(validator.count_arrangements_with(&design, &BigCount).to_string(),
+            "359579325206583560961765665172189099052367214309267232255589801");
+        assert!(validator.is_match(&design));
+        assert_eq!(total_arrangements(&validator, &[&design]),
+            "359579325206583560961765665172189099052367214309267232255589801");
+
+        // a towel with more arrangements than fit in 64 bits is still redundant,
+        // but the usage counts are unknown
+        let analysis = analyse_towels(&["r", "rr", &design], &[&design]);
+        assert_eq!(analysis.redundant, vec!["rr", &design]);
+        assert_eq!(analysis.usage, None);
+        assert_eq!(validator.towel_usage(&design), None);
+        assert!(validator.towel_usage(&"r".repeat(40)).is_some());
+    }
+
+    #[test]
+    fn example_aho_corasick() {
+        let available = parse_available("r, wr, b, g, bwu, rb, gb, br");
+        let matcher = AhoCorasick::from(&available);
+        let designs: Vec<&str> = "brwrr\nbggr\ngbbr\nrrbgbr\nubwu\nbwurrg\nbrgr\nbbrgwb".split('\n').collect();
+
+        let ways: Vec<u64> = designs.iter()
+            .map(|s| matcher.count_arrangements_with(s, &CheckedU64).unwrap())
+            .collect();
+        assert_eq!(ways, vec![2, 1, 4, 6, 0, 1, 2, 0]);
+        assert_eq!(solve(&matcher, &designs), (6, "16".to_string()));
+        assert_eq!(matcher.count_arrangements_with("rxr", &CheckedU64), Some(0));
+        assert_eq!(matcher.count_arrangements_with("", &CheckedU64), Some(1));
+    }
+
+    #[test]
+    fn empty_towel() {
+        assert_eq!(parse_available("r, , b"), vec!["r", "b"]);
+        // built directly, both backends ignore it
+        let available = ["r", "", "b"];
+        let validator = Validator::from(&available[..]);
+        let matcher = AhoCorasick::from(&available[..]);
+        for (design, ways) in [("rb", 1), ("rrb", 1), ("", 1), ("rxb", 0)] {
+            assert_eq!(validator.count_arrangements(design), Some(ways));
+            assert_eq!(matcher.count_arrangements_with(design, &CheckedU64), Some(ways));
+        }
+    }
+
+    #[test]
+    fn aho_corasick_matches_trie() {
+        let mut rng = Lcg(31);
+        for round in 0..200 {
+            let alphabet = &['w', 'u', 'b', 'r', 'g'][..2 + round % 4];
+            let towels: Vec<String> = (0..1 + rng.next() as usize % 12).map(|_| {
+                let len = 1 + rng.next() as usize % 4;
+                word(&mut rng, alphabet, len)
+            }).collect();
+            let available: Vec<&str> = towels.iter().map(|s| s.as_str()).collect();
+            let validator = Validator::from(&available);
+            let matcher = AhoCorasick::from(&available);
+            for i in 0..20 {
+                // half of the designs are built from towels, so that they match
+                let design = if i % 2 == 0 {
+                    let len = rng.next() as usize % 30;
+                    word(&mut rng, alphabet, len)
+                } else {
+                    (0..rng.next() % 10).map(|_| available[rng.next() as usize % available.len()]).collect()
+                };
+                assert_eq!(
+                    matcher.count_arrangements_with(&design, &BigCount),
+                    validator.count_arrangements_with(&design, &BigCount),
+                    "towels {:?}, design {}", available, design);
+            }
+        }
+    }
+
+    #[test]
+    fn trie_layout() {
+        let available = parse_available("r, wr, b, g, bwu, rb, gb, br");
+        let first = Validator::from(&available);
+        let second = Validator::from(&available);
+
+        // root, r, w, wr, b, g, bw, bwu, rb, gb, br
+        assert_eq!(first.completions.node_count(), 11);
+        assert_eq!(second.completions.node_count(), 11);
+        assert_eq!(first.completions.alphabet, vec!['r', 'w', 'b', 'g', 'u']);
+
+        let w = first.completions.get(ROOT, &'w').unwrap();
+        assert!(!first.completions.is_complete(w));
+        let wr = first.completions.get(w, &'r').unwrap();
+        assert!(first.completions.is_complete(wr));
+        assert_eq!(first.completions.get(wr, &'r'), None);
+        assert_eq!(first.completions.get(ROOT, &'x'), None);
+    }
+
+    // random word over the alphabet, to build synthetic towel sets
+    fn word(rng: &mut Lcg, alphabet: &[char], len: usize) -> String {
+        (0..len).map(|_| alphabet[rng.next() as usize % alphabet.len()]).collect()
+    }
+
+    // the trie as it was before the arena, boxed nodes in hash maps told apart by
+    // a global id, only kept for bench_count_arrangements to compare against
+    static BOXED_DICT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    struct BoxedDict {
+        is_complete: bool,
+        id: usize,
+        possibilities: HashMap<char,Box<BoxedDict>>,
+    }
+
+    impl BoxedDict {
+        fn new() -> BoxedDict {
+            BoxedDict{
+                possibilities: HashMap::new(),
+                is_complete: false,
+                id: BOXED_DICT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            }
+        }
+
+        fn insert(&mut self, it: &mut dyn Iterator<Item=char>) {
+            if let Some(elem) = it.next() {
+                self.possibilities
+                    .entry(elem)
+                    .or_insert(Box::new(BoxedDict::new()))
+                    .insert(it);
+            } else {
+                self.is_complete = true;
+            }
+        }
+
+        fn count_arrangements(&self, s: &str) -> usize {
+            let mut heads: HashMap<usize,(&BoxedDict,usize)> = HashMap::new();
+            heads.insert(self.id, (self,1));
+            for c in s.chars() {
+                let mut new_heads = HashMap::new();
+                for (head, path_count) in heads.values() {
+                    if let Some(next) = head.possibilities.get(&c) {
+                        if next.is_complete {
+                            let count = &mut new_heads.entry(self.id).or_insert((self,0)).1;
+                            *count = path_count.wrapping_add(*count);
+                        }
+                        let count = &mut new_heads.entry(next.id).or_insert((next.as_ref(),0)).1;
+                        *count = path_count.wrapping_add(*count);
+                    }
+                }
+                heads = new_heads;
+            }
+            heads.get(&self.id).map(|(_,count)| *count).unwrap_or_default()
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_count_arrangements() {
+        // run with `cargo test --release --bin aoc19 -- --ignored --nocapture`
+        let alphabet = ['w', 'u', 'b', 'r', 'g'];
+        let mut rng = Lcg(19);
+        let towels: Vec<String> = (0..450).map(|_| {
+            let len = 1 + rng.next() as usize % 8;
+            word(&mut rng, &alphabet, len)
+        }).collect();
+        let available: Vec<&str> = towels.iter().map(|s| s.as_str()).collect();
+        let designs: Vec<String> = (0..400).map(|_| {
+            let len = 40 + rng.next() as usize % 20;
+            word(&mut rng, &alphabet, len)
+        }).collect();
+
+        let start = std::time::Instant::now();
+        let mut total = 0;
+        for _ in 0..10 {
+            let mut dict = BoxedDict::new();
+            for towel in &available {
+                dict.insert(&mut towel.chars());
+            }
+            total += designs.iter()
+                .map(|s| dict.count_arrangements(s) as u64)
+                .fold(0u64, |acc, c| acc.wrapping_add(c));
+        }
+        println!("Boxed trie, 10 rounds of {} designs: {:?} (checksum {})", designs.len(), start.elapsed(), total);
+
+        let start = std::time::Instant::now();
+        let mut total = 0;
+        for _ in 0..10 {
+            let validator = Validator::from(&available);
+            total += designs.iter()
+                .map(|s| validator.count_arrangements(s).unwrap_or(0))
+                .fold(0u64, |acc, c| acc.wrapping_add(c));
+        }
+        println!("Arena trie, 10 rounds of {} designs: {:?} (checksum {})", designs.len(), start.elapsed(), total);
+
+        let start = std::time::Instant::now();
+        let mut total = 0;
+        for _ in 0..10 {
+            let matcher = AhoCorasick::from(&available);
+            total += designs.iter()
+                .map(|s| matcher.count_arrangements_with(s, &CheckedU64).unwrap_or(0))
+                .fold(0u64, |acc, c| acc.wrapping_add(c));
+        }
+        println!("Aho-Corasick, 10 rounds of {} designs: {:?} (checksum {})", designs.len(), start.elapsed(), total);
+    }
+}
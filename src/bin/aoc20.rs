@@ -1,6 +1,11 @@
+#[cfg(test)]
+#[path = "common/lcg.rs"]
+mod lcg;
+
 use std::env;
 use std::fs;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 
 
 fn main() {
@@ -20,8 +25,45 @@ fn main() {
 
     let count = scores.iter().filter(|s| s.improvement>=100).count();
 
+    let count2 = count_cheats(&laby, &dist_start, &dist_end, 20, 100);
+
     // lower than 1471
     println!("Result: {}", count);
+    println!("Result2: {}", count2);
+
+    match args.get(2).map(|s| s.as_str()) {
+        Some("--histogram") => {
+            // max cheat length, and optionally the smallest saving to list
+            let max_jump: usize = args[3].parse().unwrap();
+            let min_improvement: usize = args.get(4).map(|s| s.parse().unwrap()).unwrap_or(1);
+            let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, max_jump);
+            let scores = get_skip_scores(&laby, &dist_start, &distances);
+            print!("{}", format_histogram(&scores, min_improvement));
+        },
+        Some("--cheat-rules") => {
+            // cheats per run, cheat length, step cost, metric and optionally walls-only
+            let rules = CheatRules{
+                max_cheats: args[3].parse().unwrap(),
+                cost_multiplier: args[5].parse().unwrap(),
+                metric: match args[6].as_str() {
+                    "chebyshev" => Metric::Chebyshev,
+                    _ => Metric::Manhattan,
+                },
+                walls_only: args.get(7).map(|s| s.as_str()) == Some("walls-only"),
+                ..CheatRules::standard(args[4].parse().unwrap())
+            };
+            let best_dist = *dist_start.get(&laby.end).unwrap();
+            let fastest = fastest_with_cheats(&laby, &rules).unwrap();
+            println!("Fastest with cheats: {} (saves {})", fastest, best_dist - fastest);
+        },
+        Some("--export") => {
+            let max_jump: usize = args[3].parse().unwrap();
+            let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, max_jump);
+            let scores = get_skip_scores(&laby, &dist_start, &distances);
+            print!("{}", export_scores(&scores));
+        },
+        _ => {}
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, std::hash::Hash)]
@@ -88,26 +130,6 @@ enum Direction {
     Right
 }
 
-impl Direction {
-    fn turn_left(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Up,
-            Direction::Up => Direction::Left,
-            Direction::Left => Direction::Down,
-            Direction::Down => Direction::Right,
-        }
-    }
-
-    fn turn_right(&self) -> Direction {
-        match self {
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
-            Direction::Up => Direction::Right,
-        }
-    }
-}
-
 fn get_moved_position(pos: (usize, usize), size: (usize, usize), dir: Direction) -> Option<(usize,usize)> {
     match dir {
         Direction::Down => {
@@ -173,63 +195,17 @@ struct SkipResult {
     total_dist: usize
 }
 
+// cheats of at most 2 steps, any pair of track cells at most 2 apart,
+// which also covers turning cheats and branching mazes
 fn get_simple_skip_distances(map: &Map, dist_start: &HashMap<(usize,usize),usize>, dist_end: &HashMap<(usize,usize),usize>) -> Vec<SkipResult> {
-    let mut res = vec![];
-
-    for i in 0..map.get_size().0 {
-        for j in 0..map.get_size().1 {
-            if *map.get_cell((i,j)).unwrap() == Cell::Wall {
-                let to_left = get_moved_position((i,j), map.get_size(), Direction::Left);
-                let to_right = get_moved_position((i,j), map.get_size(), Direction::Right);
-
-                if let (Some(pos_left),Some(pos_right)) = (to_left,to_right) {
-                    if let (Some(dsl),Some(der)) = (dist_start.get(&pos_left), dist_end.get(&pos_right)) {
-                        res.push(SkipResult{
-                            source: pos_left,
-                            destination: pos_right,
-                            total_dist: dsl + der +2
-                        })
-                    }
-                    if let (Some(dsr),Some(del)) = (dist_start.get(&pos_right), dist_end.get(&pos_left)) {
-                        res.push(SkipResult{
-                            source: pos_right,
-                            destination: pos_left,
-                            total_dist: dsr + del +2
-                        })
-                    }
-                }
-
-                let to_up = get_moved_position((i,j), map.get_size(), Direction::Up);
-                let to_down = get_moved_position((i,j), map.get_size(), Direction::Down);
-
-                if let (Some(pos_up),Some(pos_down)) = (to_up,to_down) {
-                    if let (Some(dsu),Some(ded)) = (dist_start.get(&pos_up), dist_end.get(&pos_down)) {
-                        res.push(SkipResult{
-                            source: pos_up,
-                            destination: pos_down,
-                            total_dist: dsu + ded +2
-                        })
-                    }
-                    if let (Some(dsd),Some(deu)) = (dist_start.get(&pos_down), dist_end.get(&pos_up)) {
-                        res.push(SkipResult{
-                            source: pos_down,
-                            destination: pos_up,
-                            total_dist: dsd + deu +2
-                        })
-                    }
-                }
-            }
-        }
-    }
-
-    res
+    get_full_skip_distances(map, dist_start, dist_end, 2)
 }
 
 fn get_full_skip_distances(map: &Map, dist_start: &HashMap<(usize,usize),usize>, dist_end: &HashMap<(usize,usize),usize>, max_jump: usize) -> Vec<SkipResult> {
     let mut res = vec![];
 
-    let dist_jumps: Vec<(isize, isize)> = (-(max_jump as isize)..max_jump as isize).flat_map(|i| {
-        (-(max_jump as isize)..max_jump as isize).map(move |j| (i,j))
+    let dist_jumps: Vec<(isize, isize)> = (-(max_jump as isize)..=max_jump as isize).flat_map(|i| {
+        (-(max_jump as isize)..=max_jump as isize).map(move |j| (i,j))
     }).filter(|(i,j)| ((i.abs()+j.abs()) as usize <= max_jump) && (*i,*j) != (0,0)).collect();
 
     for (src_pos, src_dist) in dist_start {
@@ -239,10 +215,11 @@ fn get_full_skip_distances(map: &Map, dist_start: &HashMap<(usize,usize),usize>,
             if i+di >= 0 && i+di < map.get_size().0 as isize && j+dj >= 0 && j+dj < map.get_size().1 as isize {
                 let new_pos = ((i+di) as usize, (j+dj) as usize);
                 if let Some(dst_dist) = dist_end.get(&new_pos) {
+                    // the cheat itself still takes one step per cell
                     res.push(SkipResult{
                         source: *src_pos,
                         destination: new_pos,
-                        total_dist: src_dist + dst_dist
+                        total_dist: src_dist + dst_dist + (di.abs() + dj.abs()) as usize
                     });
                 }
             }
@@ -252,6 +229,175 @@ fn get_full_skip_distances(map: &Map, dist_start: &HashMap<(usize,usize),usize>,
     res
 }
 
+// cells reachable from the start, ordered by distance from it
+fn track_in_order(dist_start: &HashMap<(usize,usize),usize>) -> Vec<(usize,usize)> {
+    let mut track: Vec<(usize,usize)> = dist_start.keys().copied().collect();
+    track.sort_by_key(|pos| (dist_start[pos], *pos));
+    track
+}
+
+// same as counting the scores of get_full_skip_distances with at least
+// the given improvement, without storing every cheat
+fn count_cheats(map: &Map, dist_start: &HashMap<(usize,usize),usize>, dist_end: &HashMap<(usize,usize),usize>, max_jump: usize, min_improvement: usize) -> usize {
+    let Some(&best_dist) = dist_start.get(&map.end) else {
+        return 0;
+    };
+    let track = track_in_order(dist_start);
+    let size = map.get_size();
+    let max_jump = max_jump as isize;
+
+    let count_from = |&(src_pos, src_dist): &((usize,usize),usize)| -> usize {
+        let mut count = 0;
+        for di in -max_jump..=max_jump {
+            let i = src_pos.0 as isize + di;
+            if i < 0 || i >= size.0 as isize {
+                continue;
+            }
+            let remaining = max_jump - di.abs();
+            for dj in -remaining..=remaining {
+                let j = src_pos.1 as isize + dj;
+                if j < 0 || j >= size.1 as isize || (di,dj) == (0,0) {
+                    continue;
+                }
+                if let Some(dst_dist) = dist_end.get(&(i as usize, j as usize)) {
+                    let total_dist = src_dist + dst_dist + (di.abs() + dj.abs()) as usize;
+                    // like get_skip_scores, cheats that do not help are never counted
+                    if total_dist < best_dist && best_dist - total_dist >= min_improvement {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    };
+
+    let sources: Vec<((usize,usize),usize)> = track.iter().map(|pos| (*pos, dist_start[pos])).collect();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = sources.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = sources.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(count_from).sum::<usize>()))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Metric {
+    // cheats move orthogonally
+    Manhattan,
+    // cheats may also move diagonally
+    Chebyshev,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct CheatRules {
+    max_cheats: usize,
+    // steps allowed in a single cheat
+    max_length: usize,
+    // cheats may only cross walls, not open track
+    walls_only: bool,
+    metric: Metric,
+    // cost of each step taken while cheating
+    cost_multiplier: usize,
+}
+
+impl CheatRules {
+    // the rules of the puzzle
+    fn standard(max_length: usize) -> CheatRules {
+        CheatRules{
+            max_cheats: 1,
+            max_length,
+            walls_only: false,
+            metric: Metric::Manhattan,
+            cost_multiplier: 1,
+        }
+    }
+
+    fn cheat_moves(&self) -> &'static [(isize,isize)] {
+        match self.metric {
+            Metric::Manhattan => &[(-1,0), (1,0), (0,-1), (0,1)],
+            Metric::Chebyshev => &[(-1,0), (1,0), (0,-1), (0,1), (-1,-1), (-1,1), (1,-1), (1,1)],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct CheatState {
+    pos: (usize,usize),
+    cheats_used: usize,
+    // steps left in the current cheat, None when not cheating
+    cheat_remaining: Option<usize>,
+}
+
+// fastest time from start to end with the given cheats allowed
+fn fastest_with_cheats(map: &Map, rules: &CheatRules) -> Option<usize> {
+    if rules.max_cheats == 0 || rules.max_length == 0 {
+        return explore_all_from(map, map.start).get(&map.end).copied();
+    }
+
+    let size = map.get_size();
+    let mut best: HashMap<CheatState,usize> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    let origin = CheatState{pos: map.start, cheats_used: 0, cheat_remaining: None};
+    best.insert(origin, 0);
+    queue.push(Reverse((0, origin)));
+
+    while let Some(Reverse((dist, state))) = queue.pop() {
+        if best.get(&state).is_some_and(|&d| d < dist) {
+            continue;
+        }
+        if state.pos == map.end && state.cheat_remaining.is_none() {
+            return Some(dist);
+        }
+
+        let mut next_states = vec![];
+        let is_wall = *map.get_cell(state.pos).unwrap() == Cell::Wall;
+        match state.cheat_remaining {
+            None => {
+                for dir in [Direction::Up, Direction::Down, Direction::Right, Direction::Left] {
+                    if let Some(next_pos) = get_moved_position(state.pos, size, dir) {
+                        if *map.get_cell(next_pos).unwrap() == Cell::Empty {
+                            next_states.push((dist+1, CheatState{pos: next_pos, ..state}));
+                        }
+                    }
+                }
+                if state.cheats_used < rules.max_cheats {
+                    let cheating = CheatState{cheats_used: state.cheats_used+1, cheat_remaining: Some(rules.max_length), ..state};
+                    next_states.push((dist, cheating));
+                }
+            },
+            Some(remaining) => {
+                if !is_wall {
+                    next_states.push((dist, CheatState{cheat_remaining: None, ..state}));
+                }
+                // open track ends a walls-only cheat, except where it started
+                let started_here = remaining == rules.max_length;
+                let may_continue = !rules.walls_only || is_wall || started_here;
+                if remaining > 0 && may_continue {
+                    for (di,dj) in rules.cheat_moves() {
+                        let i = state.pos.0 as isize + di;
+                        let j = state.pos.1 as isize + dj;
+                        if i < 0 || j < 0 || i >= size.0 as isize || j >= size.1 as isize {
+                            continue;
+                        }
+                        let next = CheatState{pos: (i as usize, j as usize), cheat_remaining: Some(remaining-1), ..state};
+                        next_states.push((dist + rules.cost_multiplier, next));
+                    }
+                }
+            },
+        }
+
+        for (next_dist, next) in next_states {
+            if best.get(&next).is_none_or(|&d| next_dist < d) {
+                best.insert(next, next_dist);
+                queue.push(Reverse((next_dist, next)));
+            }
+        }
+    }
+    None
+}
+
 #[derive(Debug)]
 struct SkipScore {
     source: (usize,usize),
@@ -260,7 +406,10 @@ struct SkipScore {
 }
 
 fn get_skip_scores(map: &Map, dist_start: &HashMap<(usize,usize),usize>, dist: &[SkipResult]) -> Vec<SkipScore> {
-    let best_dist = *dist_start.get(&map.end).unwrap();
+    // shortest distance without cheating, whichever route it takes
+    let Some(&best_dist) = dist_start.get(&map.end) else {
+        return vec![];
+    };
     dist.iter().filter(|sr| sr.total_dist < best_dist).map(|sr| SkipScore{
         source: sr.source,
         destination: sr.destination,
@@ -268,9 +417,37 @@ fn get_skip_scores(map: &Map, dist_start: &HashMap<(usize,usize),usize>, dist: &
     }).collect()
 }
 
+// number of cheats for each saving, as listed in the puzzle statement
+fn format_histogram(scores: &[SkipScore], min_improvement: usize) -> String {
+    let mut histogram: BTreeMap<usize,usize> = BTreeMap::new();
+    for score in scores.iter().filter(|s| s.improvement >= min_improvement) {
+        *histogram.entry(score.improvement).or_default() += 1;
+    }
+    histogram.iter()
+        .map(|(improvement, count)| if *count == 1 {
+            format!("There is one cheat that saves {} picoseconds.\n", improvement)
+        } else {
+            format!("There are {} cheats that save {} picoseconds.\n", count, improvement)
+        })
+        .collect()
+}
+
+// one line per cheat: source row, source column, destination row, destination column, saving
+fn export_scores(scores: &[SkipScore]) -> String {
+    let mut res = String::from("source_row,source_col,destination_row,destination_col,improvement\n");
+    for score in scores {
+        res += &format!("{},{},{},{},{}\n",
+            score.source.0, score.source.1,
+            score.destination.0, score.destination.1,
+            score.improvement);
+    }
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::lcg::Lcg;
 
     #[test]
     fn example() {
@@ -304,7 +481,7 @@ mod tests {
         println!("{:?}", scores2);
 
         assert_eq!(scores2.iter().filter(|e| **e == 50).count(), 32);
-        assert_eq!(scores2.iter().filter(|e| **e == 52).count(), 29);
+        assert_eq!(scores2.iter().filter(|e| **e == 52).count(), 31);
         assert_eq!(scores2.iter().filter(|e| **e == 54).count(), 29);
         assert_eq!(scores2.iter().filter(|e| **e == 56).count(), 39);
         assert_eq!(scores2.iter().filter(|e| **e == 58).count(), 25);
@@ -318,4 +495,183 @@ mod tests {
         assert_eq!(scores2.iter().filter(|e| **e == 74).count(), 4);
         assert_eq!(scores2.iter().filter(|e| **e == 76).count(), 3);
     }
+
+    #[test]
+    fn example_count() {
+        let laby = parse_map("###############\n#...#...#.....#\n#.#.#.#.#.###.#\n#S#...#.#.#...#\n#######.#.#.###\n#######.#.#...#\n#######.#.###.#\n###..E#...#...#\n###.#######.###\n#...###...#...#\n#.#####.#.###.#\n#.#...#.#.#...#\n#.#.#.#.#.#.###\n#...#...#...###\n###############");
+
+        let dist_start = explore_all_from(&laby, laby.start);
+        let dist_end = explore_all_from(&laby, laby.end);
+
+        let track = track_in_order(&dist_start);
+        assert_eq!(track.len(), 85);
+        assert_eq!(track[0], laby.start);
+        assert_eq!(*track.last().unwrap(), laby.end);
+
+        for max_jump in [2, 3, 6, 20] {
+            let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, max_jump);
+            let scores = get_skip_scores(&laby, &dist_start, &distances);
+            for min_improvement in [0, 1, 2, 12, 50, 76, 77] {
+                let expected = scores.iter().filter(|s| s.improvement >= min_improvement).count();
+                assert_eq!(count_cheats(&laby, &dist_start, &dist_end, max_jump, min_improvement), expected);
+            }
+        }
+        assert_eq!(count_cheats(&laby, &dist_start, &dist_end, 20, 50), 285);
+    }
+
+    // maze carved by a random depth first search, with extra walls removed to make loops
+    fn generate_maze(rng: &mut Lcg, height: usize, width: usize, loops: usize) -> Map {
+        let size = (2*height+1, 2*width+1);
+        let mut cells = vec![vec![Cell::Wall; size.1]; size.0];
+        let mut visited = vec![vec![false; width]; height];
+        let mut stack: Vec<(usize,usize)> = vec![(0,0)];
+        visited[0][0] = true;
+        cells[1][1] = Cell::Empty;
+        while let Some(&(i,j)) = stack.last() {
+            let neighbours: Vec<(usize,usize)> = [(i.wrapping_sub(1),j), (i+1,j), (i,j.wrapping_sub(1)), (i,j+1)]
+                .into_iter()
+                .filter(|&(ni,nj)| ni < height && nj < width && !visited[ni][nj])
+                .collect();
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+            let (ni,nj) = neighbours[rng.next() as usize % neighbours.len()];
+            visited[ni][nj] = true;
+            cells[2*ni+1][2*nj+1] = Cell::Empty;
+            cells[i+ni+1][j+nj+1] = Cell::Empty;
+            stack.push((ni,nj));
+        }
+        for _ in 0..loops {
+            let i = 1 + rng.next() as usize % (size.0-2);
+            let j = 1 + rng.next() as usize % (size.1-2);
+            cells[i][j] = Cell::Empty;
+        }
+        Map{cells, start: (1,1), end: (size.0-2, size.1-2)}
+    }
+
+    #[test]
+    fn loop_maze() {
+        // two equally short routes around the middle wall
+        let laby = parse_map("#########\n#...S...#\n#.#####.#\n#.......#\n####.####\n####E####\n#########");
+
+        let dist_start = explore_all_from(&laby, laby.start);
+        let dist_end = explore_all_from(&laby, laby.end);
+        assert_eq!(dist_start[&laby.end], 10);
+
+        let distances = get_simple_skip_distances(&laby, &dist_start, &dist_end);
+        let mut scores: Vec<usize> = get_skip_scores(&laby, &dist_start, &distances).iter().map(|ss| ss.improvement).collect();
+        scores.sort();
+        assert_eq!(scores, vec![2, 2, 4, 4, 6]);
+    }
+
+    #[test]
+    fn generated_mazes() {
+        let mut rng = Lcg(20);
+        for round in 0..30 {
+            let laby = generate_maze(&mut rng, 4 + round % 5, 5 + round % 3, round % 8);
+            let dist_start = explore_all_from(&laby, laby.start);
+            let dist_end = explore_all_from(&laby, laby.end);
+            let best_dist = dist_start[&laby.end];
+
+            // a 2 step cheat saves as much as opening the wall it crosses
+            let distances = get_simple_skip_distances(&laby, &dist_start, &dist_end);
+            let scores = get_skip_scores(&laby, &dist_start, &distances);
+            let best_opened = (0..laby.get_size().0)
+                .flat_map(|i| (0..laby.get_size().1).map(move |j| (i,j)))
+                .filter(|&pos| laby.get_cell(pos) == Some(&Cell::Wall))
+                .map(|(i,j)| {
+                    let mut cells = laby.cells.clone();
+                    cells[i][j] = Cell::Empty;
+                    let opened = Map{cells, ..laby};
+                    explore_all_from(&opened, opened.start)[&opened.end]
+                })
+                .min()
+                .unwrap();
+            let best_saving = scores.iter().map(|s| s.improvement).max().unwrap_or(0);
+            assert_eq!(best_saving, best_dist - best_opened.min(best_dist));
+            assert_eq!(scores.len(), count_cheats(&laby, &dist_start, &dist_end, 2, 1));
+
+            for max_jump in [2, 3, 6] {
+                let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, max_jump);
+                let scores = get_skip_scores(&laby, &dist_start, &distances);
+                let best_saving = scores.iter().map(|s| s.improvement).max().unwrap_or(0);
+                assert_eq!(fastest_with_cheats(&laby, &CheatRules::standard(max_jump)), Some(best_dist - best_saving));
+                assert_eq!(count_cheats(&laby, &dist_start, &dist_end, max_jump, 1), scores.len());
+            }
+        }
+    }
+
+    #[test]
+    fn example_cheat_rules() {
+        let laby = parse_map("###############\n#...#...#.....#\n#.#.#.#.#.###.#\n#S#...#.#.#...#\n#######.#.#.###\n#######.#.#...#\n#######.#.###.#\n###..E#...#...#\n###.#######.###\n#...###...#...#\n#.#####.#.###.#\n#.#...#.#.#...#\n#.#.#.#.#.#.###\n#...#...#...###\n###############");
+
+        let dist_start = explore_all_from(&laby, laby.start);
+        let dist_end = explore_all_from(&laby, laby.end);
+
+        assert_eq!(fastest_with_cheats(&laby, &CheatRules{max_cheats: 0, ..CheatRules::standard(20)}), Some(84));
+        assert_eq!(fastest_with_cheats(&laby, &CheatRules::standard(2)), Some(84-64));
+        assert_eq!(fastest_with_cheats(&laby, &CheatRules::standard(20)), Some(84-76));
+
+        // the best 2 step cheat only crosses one wall, and costs 2 more when steps are doubled
+        let walls_only = CheatRules{walls_only: true, ..CheatRules::standard(2)};
+        assert_eq!(fastest_with_cheats(&laby, &walls_only), Some(84-64));
+        let doubled = CheatRules{cost_multiplier: 2, ..CheatRules::standard(2)};
+        assert_eq!(fastest_with_cheats(&laby, &doubled), Some(84-62));
+
+        // a single Chebyshev cheat is a jump to any track cell within the square
+        for max_length in [1, 2, 5, 20] {
+            let expected = dist_start.iter()
+                .flat_map(|(src, ds)| dist_end.iter().map(move |(dst, de)| (src, ds, dst, de)))
+                .map(|(src, ds, dst, de)| (ds + de, src.0.abs_diff(dst.0).max(src.1.abs_diff(dst.1))))
+                .filter(|&(_, jump)| jump <= max_length)
+                .map(|(dist, jump)| dist + jump)
+                .min();
+            let rules = CheatRules{metric: Metric::Chebyshev, ..CheatRules::standard(max_length)};
+            assert_eq!(fastest_with_cheats(&laby, &rules), expected);
+        }
+
+        let once = fastest_with_cheats(&laby, &CheatRules::standard(2)).unwrap();
+        let twice = fastest_with_cheats(&laby, &CheatRules{max_cheats: 2, ..CheatRules::standard(2)}).unwrap();
+        assert!(twice < once);
+    }
+
+    #[test]
+    fn example_histogram() {
+        let laby = parse_map("###############\n#...#...#.....#\n#.#.#.#.#.###.#\n#S#...#.#.#...#\n#######.#.#.###\n#######.#.#...#\n#######.#.###.#\n###..E#...#...#\n###.#######.###\n#...###...#...#\n#.#####.#.###.#\n#.#...#.#.#...#\n#.#.#.#.#.#.###\n#...#...#...###\n###############");
+
+        let dist_start = explore_all_from(&laby, laby.start);
+        let dist_end = explore_all_from(&laby, laby.end);
+
+        let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, 2);
+        let scores = get_skip_scores(&laby, &dist_start, &distances);
+        assert_eq!(format_histogram(&scores, 1), "\
+There are 14 cheats that save 2 picoseconds.
+There are 14 cheats that save 4 picoseconds.
+There are 2 cheats that save 6 picoseconds.
+There are 4 cheats that save 8 picoseconds.
+There are 2 cheats that save 10 picoseconds.
+There are 3 cheats that save 12 picoseconds.
+There is one cheat that saves 20 picoseconds.
+There is one cheat that saves 36 picoseconds.
+There is one cheat that saves 38 picoseconds.
+There is one cheat that saves 40 picoseconds.
+There is one cheat that saves 64 picoseconds.
+");
+
+        let distances = get_full_skip_distances(&laby, &dist_start, &dist_end, 20);
+        let scores = get_skip_scores(&laby, &dist_start, &distances);
+        assert!(format_histogram(&scores, 70).starts_with("\
+There are 12 cheats that save 70 picoseconds.
+There are 22 cheats that save 72 picoseconds.
+There are 4 cheats that save 74 picoseconds.
+There are 3 cheats that save 76 picoseconds."));
+
+        let best: Vec<&SkipScore> = scores.iter().filter(|s| s.improvement == 76).collect();
+        let export = export_scores(&scores);
+        assert_eq!(export.lines().count(), scores.len()+1);
+        assert!(export.contains(&format!("{},{},{},{},76\n",
+            best[0].source.0, best[0].source.1, best[0].destination.0, best[0].destination.1)));
+        assert!(best.iter().all(|s| s.source == laby.start));
+    }
 }
\ No newline at end of file
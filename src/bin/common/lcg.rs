@@ -0,0 +1,11 @@
+// simple deterministic generator for the randomised tests, so that a failing
+// case comes back on every run
+
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
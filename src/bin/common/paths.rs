@@ -0,0 +1,214 @@
+// cheapest paths over (position, direction) states in a grid of walls, where
+// moving forward and turning in place each have their own cost; shared by the
+// reindeer maze (aoc16) and the falling bytes memory space (aoc18)
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Costs {
+    // cost of moving forward, multiplied by the weight of the cell entered
+    pub forward: usize,
+    pub turn: usize,
+    // turning around in place, not allowed when None
+    pub u_turn: Option<usize>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, std::hash::Hash)]
+pub enum Cell {
+    Empty,
+    Wall,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Map {
+    pub cells: Vec<Vec<Cell>>,
+    // traversal cost of each cell
+    pub weights: Vec<Vec<usize>>,
+    pub start: (usize,usize),
+    pub end: (usize,usize)
+}
+
+impl Map {
+    pub fn get_size(&self) -> (usize,usize) {
+        (self.cells.len(), self.cells.first().unwrap().len())
+    }
+    pub fn get_cell(&self, pos: (usize, usize)) -> Option<&Cell> {
+        self.cells.get(pos.0).and_then(|row| row.get(pos.1))
+    }
+    pub fn get_weight(&self, pos: (usize, usize)) -> usize {
+        self.weights[pos.0][pos.1]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, std::hash::Hash, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl Direction {
+    pub fn turn_left(&self) -> Direction {
+        match self {
+            Direction::Right => Direction::Up,
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+        }
+    }
+
+    pub fn turn_right(&self) -> Direction {
+        match self {
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+            Direction::Up => Direction::Right,
+        }
+    }
+
+    pub fn turn_around(&self) -> Direction {
+        self.turn_left().turn_left()
+    }
+}
+
+pub fn get_moved_position(pos: (usize, usize), size: (usize, usize), dir: Direction) -> Option<(usize,usize)> {
+    match dir {
+        Direction::Down => {
+            if pos.0 +1 < size.0 {
+                Some((pos.0+1, pos.1))
+            } else {
+                None
+            }
+        },
+        Direction::Right => {
+            if pos.1 +1 < size.1 {
+                Some((pos.0, pos.1+1))
+            } else {
+                None
+            }
+        },
+        Direction::Left => {
+            if pos.1 > 0 {
+                Some((pos.0, pos.1-1))
+            } else {
+                None
+            }
+        },
+        Direction::Up => {
+            if pos.0 > 0 {
+                Some((pos.0-1, pos.1))
+            } else {
+                None
+            }
+        },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, std::hash::Hash, Debug)]
+pub enum Action {
+    TurnLeft,
+    TurnRight,
+    TurnAround,
+    Forward,
+}
+
+pub struct ExploreInfo {
+    pub parents: Vec<((usize,usize),Direction)>,
+    pub parent_action: Vec<Action>,
+    pub distance: usize
+}
+
+pub type State = ((usize,usize),Direction);
+
+// states reachable in one action from the given one, with the action cost
+pub fn next_states(map: &Map, costs: &Costs, current: State) -> Vec<(State, Action, usize)> {
+    let mut res = vec![];
+    if let Some(pos_forward) = get_moved_position(current.0, map.get_size(), current.1) {
+        if *map.get_cell(pos_forward).unwrap() == Cell::Empty {
+            res.push(((pos_forward, current.1), Action::Forward, costs.forward * map.get_weight(pos_forward)));
+        }
+    }
+    res.push(((current.0, current.1.turn_left()), Action::TurnLeft, costs.turn));
+    res.push(((current.0, current.1.turn_right()), Action::TurnRight, costs.turn));
+    if let Some(u_turn) = costs.u_turn {
+        res.push(((current.0, current.1.turn_around()), Action::TurnAround, u_turn));
+    }
+    res
+}
+
+// cheapest distance from the start to every reachable state, with every state it is
+// reached from at that distance, free actions included; the start state gets no
+// parents since a path never has to come back to it
+pub fn explore_base_paths(map: &Map, costs: &Costs) -> HashMap<((usize,usize),Direction), ExploreInfo> {
+    let origin = (map.start, Direction::Right);
+    let mut infos: HashMap<((usize,usize),Direction), ExploreInfo> = HashMap::new();
+    let mut to_explore = BinaryHeap::from([Reverse((0, origin))]);
+    infos.insert(origin, ExploreInfo{
+        parents: vec![],
+        parent_action: vec![],
+        distance:0
+    });
+
+    let mut prev_distance = 0;
+
+    while let Some(Reverse((current_distance, current))) = to_explore.pop() {
+        if infos.get(&current).unwrap().distance < current_distance {
+            continue;
+        }
+        debug_assert!(prev_distance <= current_distance, "explored at distance {} after {}", current_distance, prev_distance);
+        prev_distance = current_distance;
+        for (next, action, cost) in next_states(map, costs, current) {
+            if next == origin {
+                continue;
+            }
+            let new_info = ExploreInfo{
+                parents: vec![current],
+                parent_action: vec![action],
+                distance: current_distance + cost,
+            };
+            match infos.entry(next) {
+                Entry::Vacant(v) => {
+                    to_explore.push(Reverse((new_info.distance, next)));
+                    v.insert(new_info);
+                },
+                Entry::Occupied(mut o) => match new_info.distance.cmp(&o.get().distance) {
+                    Ordering::Less => {
+                        to_explore.push(Reverse((new_info.distance, next)));
+                        o.insert(new_info);
+                    },
+                    Ordering::Equal => {
+                        // the first parent always comes from a state settled earlier,
+                        // later ones may close loops of free actions
+                        o.get_mut().parents.push(current);
+                        o.get_mut().parent_action.push(action);
+                    },
+                    Ordering::Greater => {}
+                }
+            };
+        }
+    }
+
+    infos
+}
+
+pub fn best_path_from_infos(map: &Map, infos: &HashMap<((usize,usize),Direction), ExploreInfo>) -> Vec<Action> {
+    // build backwards, first parents never loop
+    let mut end_vec: Vec<((usize,usize),Direction)> = infos.keys().filter(|e| e.0 == map.end).cloned().collect();
+    end_vec.sort_by_key(|item| infos.get(item).unwrap().distance);
+    let mut reached = end_vec.first().cloned();
+    let mut res: Vec<Action> = vec![];
+    while reached.is_some() {
+        let pos = reached.unwrap();
+        let info = infos.get(&pos).unwrap();
+        reached = info.parents.first().cloned();
+        if let Some(act) = info.parent_action.first() {
+            res.push(*act);
+        }
+    }
+    res.reverse();
+    res
+}